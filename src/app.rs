@@ -1,9 +1,10 @@
 use core::fmt::Formatter;
 use std::error::Error;
 use std::{
-    env::current_dir,
+    env::{self, current_dir},
     path::{Path, PathBuf},
 };
+use crate::{bookmarks::Bookmarks, create::CreatePrompt, link::{self, LinkKind}, cursor::Cursors, entry::Entry, goto::normalize, history::History, jobs::{Clipboard, JobKind, Jobs, Outcome}, journal::{Journal, OpKind, Revert, Stamp, Step}, jump::JumpDb, rename, selection::Selection, sort::SortOrder};
 
 #[cfg(target_family = "unix")]
 pub const PATH_SEAPARATOR: &str = "/";
@@ -30,213 +31,429 @@ fn app_error(msg: &str) -> Box<AppError> {
     Box::new(AppError { msg: msg.to_string() })
 }
 
+/// Dotfiles are considered hidden
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
 pub struct App {
     cwd: PathBuf,
-    history: Vec<PathBuf>,
+    start_selection: Option<PathBuf>,
+    cursors: Cursors,
+    history: History,
     dirs_only: bool,
+    show_hidden: bool,
+    sort: SortOrder,
+    jumps: JumpDb,
+    bookmarks: Bookmarks,
+    jobs: Jobs,
+    clipboard: Option<Clipboard>,
+    selection: Selection,
+    journal: Journal,
+    /// The job undoing or redoing an operation, by its id, and which it does
+    reverting: Option<(u64, bool)>,
 }
 impl App {
-    pub fn new(path: Option<String>, dirs_only: bool) -> Result<Self, Box<dyn Error>> {
-        let cwd = path
-            .and_then(|p| {
-                let pathbuf = PathBuf::from(p);
-                if pathbuf.exists() {
-                    Some(pathbuf)
-                } else { None }
-            })
-            .or_else(|| {
-                Some(current_dir().expect("could not determine current directory"))
-            })
-            .expect("Invalid path supplied");
+    pub fn new(path: Option<String>, dirs_only: bool, show_hidden: bool, sort: SortOrder) -> Result<Self, Box<dyn Error>> {
+        let bookmarks = Bookmarks::load();
+        let working_dir = current_dir()
+            .map_err(|e| app_error(&format!("Could not determine the current directory: {}", e)))?;
+        let path = match path {
+            None => working_dir.clone(),
+            // `-` is the previous directory of the shell, like `cd -`
+            Some(p) if p == "-" => {
+                let oldpwd = env::var_os("OLDPWD")
+                    .filter(|p| !p.is_empty())
+                    .ok_or_else(|| app_error("OLDPWD is not set"))?;
+                PathBuf::from(oldpwd)
+            }
+            // `@name` starts in a bookmarked directory
+            Some(p) if p.starts_with('@') => {
+                let name = &p[1..];
+                bookmarks.get(name)
+                    .ok_or_else(|| app_error(&format!("No bookmark named '{}'", name)))?
+                    .to_path_buf()
+            }
+            Some(p) => PathBuf::from(p),
+        };
+        let path = normalize(&working_dir.join(path));
+        let metadata = path.metadata()
+            .map_err(|e| app_error(&format!("{}: {}", path.display(), e)))?;
+        // A file opens its directory with the file selected
+        let (cwd, start_selection) = if metadata.is_dir() {
+            (path, None)
+        } else {
+            let parent = path.parent()
+                .ok_or_else(|| app_error(&format!("{}: has no parent directory", path.display())))?
+                .to_path_buf();
+            (parent, Some(path))
+        };
+        let mut jumps = JumpDb::load();
+        jumps.visit(&cwd);
         Ok(App {
-            cwd: cwd,
-            history: vec![],
+            history: History::new(&cwd),
+            cwd,
+            start_selection,
+            cursors: Cursors::load(),
             dirs_only,
+            show_hidden,
+            sort,
+            jumps,
+            bookmarks,
+            jobs: Jobs::new(),
+            clipboard: None,
+            selection: Selection::default(),
+            journal: Journal::load(),
+            reverting: None,
         })
     }
 
+    /// The file given on the command line, if it was a file rather than a directory
+    pub fn take_start_selection(&mut self) -> Option<PathBuf> {
+        self.start_selection.take()
+    }
+
     pub fn is_dirs_only(&self) -> bool {
         self.dirs_only
     }
 
-    fn strip_path_string(&self, path: &Path) -> Result<String, Box<dyn Error>> {
-        let p = &path
-            .to_str()
-            .ok_or(app_error("Couldn't convert path to string"))?
-            .split(PATH_SEAPARATOR)
-            .collect::<Vec<&str>>();
-        let s = p[p.len() - 1];
+    pub fn shows_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
+    /// Callers are expected to re-list afterwards
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+    }
 
-        if path.metadata()?.is_dir() {
-            return Ok(s.to_string() + PATH_SEAPARATOR)
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort
+    }
+
+    /// Changes how listings are ordered, callers are expected to re-list afterwards
+    pub fn set_sort_order(&mut self, sort: SortOrder) {
+        self.sort = sort;
+    }
+
+    fn folder_name(&self, path: &Path) -> String {
+        match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned() + PATH_SEAPARATOR,
+            None => path.display().to_string(),
         }
-        Ok(s.to_string())
     }
 
-    fn list_path_children(&self, path: &PathBuf) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    fn list_path_children(&self, path: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
         if let Ok(m) = path.metadata() {
             if !m.is_dir() { return Err(app_error("Path is not a directory")) }
         }
         let mut result = vec!();
-        for path in path.read_dir() {
-            for item in path {
-                if let Ok(item) = item {
-                    let p = item.path();
-                    if let Ok(m) = p.as_path().metadata() {
-                        if !(self.dirs_only && !m.is_dir()) {
-                            result.push(p);
-                        }
+        if let Ok(dir) = path.read_dir() {
+            for item in dir.flatten() {
+                if !self.show_hidden && is_hidden(&item.path()) {
+                    continue;
+                }
+                if let Ok(entry) = Entry::from_dir_entry(&item) {
+                    if !self.dirs_only || entry.is_dir {
+                        result.push(entry);
                     }
                 }
             }
         }
-        return Ok(result);
+        self.sort.sort(&mut result);
+        Ok(result)
+    }
+
+    pub fn list_cwd_children(&self) -> Result<Vec<Entry>, Box<dyn Error>> {
+        self.list_path_children(&self.cwd)
     }
 
-    fn list_path_children_names(&self, path: &PathBuf) -> Result<Vec<String>, Box<dyn Error>> {
-        Ok(
-            self.list_path_children(path)?
-            .iter().map(|p| 
-                self.strip_path_string(p)
-                    .ok()
-                    .unwrap_or("???".to_string())
-            ).collect()
+    pub fn parent_children(&self) -> Result<Vec<Entry>, Box<dyn Error>> {
+        self.list_path_children(
+            self.cwd.parent().ok_or_else(|| app_error("No parent while trying to list parent's children"))?
         )
     }
 
-    fn path_nth_child(&self, path: &PathBuf, idx: usize) -> Result<PathBuf, Box<dyn Error>> {
-        Ok(self.list_path_children(path)?
-            .get(idx)
-            .ok_or(app_error("No such child"))?.clone())
+    pub fn current_folder_name(&self) -> String {
+        self.folder_name(&self.cwd)
     }
 
-    pub fn list_cwd_child_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        self.list_path_children_names(&self.cwd)
+    pub fn parent_folder_name(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.folder_name(
+            self.cwd.parent().ok_or_else(|| app_error("No parent"))?
+        ))
     }
 
-    pub fn parent_children_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        self.list_path_children_names(
-            &self.cwd.parent().ok_or(app_error("No parent while trying to list parent's children"))?.to_path_buf()
-        )
+    pub fn list_entry_children(&self, entry: &Entry) -> Result<Vec<Entry>, Box<dyn Error>> {
+        if !entry.is_dir {
+            return Err(app_error("Child is not a folder"));
+        }
+        self.list_path_children(&entry.path)
     }
 
-    pub fn current_folder_name(&self) -> Result<String, Box<dyn Error>> {
-        self.strip_path_string(self.cwd.as_path())
+    /// Position of the current directory in the given listing of its parent
+    pub fn cwd_parent_idx(&self, parent: &[Entry]) -> Option<usize> {
+        parent.iter().position(|e| e.path == self.cwd)
     }
 
-    pub fn parent_folder_name(&self) -> Result<String, Box<dyn Error>> {
-        self.strip_path_string(
-            self.cwd.parent().ok_or(app_error("No parent"))?
-        )
+    /// Moves to `path`, recording the visit.
+    /// `selected` is the entry selected in the directory being left.
+    fn enter(&mut self, path: PathBuf, selected: Option<&Path>) {
+        self.remember_selection(selected);
+        self.history.push(selected, &path);
+        self.cwd = path;
+        self.jumps.visit(&self.cwd);
+    }
+
+    pub fn up(&mut self, selected: Option<&Entry>) -> Result<(), Box<dyn Error>> {
+        let parent = self.cwd.parent().ok_or_else(|| app_error("No parent"))?.to_path_buf();
+        self.enter(parent, selected.map(|e| e.path.as_path()));
+        Ok(())
     }
 
-    pub fn list_cwd_nth_child_children_names(&self, idx: usize) -> Result<Vec<String>, Box<dyn Error>> {
-        if !self.child_is_folder(idx) {
+    pub fn down(&mut self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        if !entry.is_dir {
             return Err(app_error("Child is not a folder"));
         }
-        let children = self.list_path_children(&self.cwd)?;
-        let child = children.get(idx).ok_or(app_error("No such child"))?;
-        self.list_path_children_names(&child.to_path_buf())
+        self.enter(entry.path.clone(), Some(&entry.path));
+        Ok(())
     }
 
-    pub fn cwd_parent_idx(&self) -> Result<usize, Box<dyn Error>> {
-        let parent = self.parent_children_names()?;
-        let curr = self.current_folder_name()?;
-        for (i, item) in parent.iter().enumerate() {
-            if item == &curr {
-                return Ok(i);
-            }
+    /// Moves straight to the given directory
+    pub fn jump(&mut self, path: &Path, selected: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        if !path.is_dir() {
+            return Err(app_error("Not a directory"));
         }
-        Err(app_error("Not found"))
+        self.enter(path.to_path_buf(), selected);
+        Ok(())
     }
 
-    pub fn child_is_folder(&self, idx: usize) -> bool {
-        if self.dirs_only { return true; }
+    /// Goes back in the session history, returns the entry which was selected there
+    pub fn back(&mut self, selected: Option<&Path>) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        self.remember_selection(selected);
+        let visit = self.history.back(selected).ok_or_else(|| app_error("Nothing to go back to"))?;
+        let (path, selected) = (visit.path.clone(), visit.selected.clone());
+        self.cwd = path;
+        self.jumps.visit(&self.cwd);
+        Ok(selected)
+    }
 
-        self.path_nth_child(&self.cwd, idx)
-            .and_then(|p| {
-                Ok(p.metadata()?.is_dir())
-            })
-            .unwrap_or(false)
+    /// Goes forward in the session history, returns the entry which was selected there
+    pub fn forward(&mut self, selected: Option<&Path>) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        self.remember_selection(selected);
+        let visit = self.history.forward(selected).ok_or_else(|| app_error("Nothing to go forward to"))?;
+        let (path, selected) = (visit.path.clone(), visit.selected.clone());
+        self.cwd = path;
+        self.jumps.visit(&self.cwd);
+        Ok(selected)
     }
 
-    pub fn up(&mut self, selected_idx: Option<usize>) -> Result<(), Box<dyn Error>> {
-        self.path_nth_child(
-            &self.cwd,
-            selected_idx.unwrap_or_default()
-        ).and_then(|i| {
-                Ok(self.history.push((*i).to_path_buf()))
-        }).ok();
-        self.cwd = self.cwd.parent().ok_or(app_error("No parent"))?.to_path_buf();
-        Ok(())
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn bookmarks(&self) -> &Bookmarks {
+        &self.bookmarks
     }
 
-    pub fn down(&mut self, idx: usize) -> Result<Vec<String>, Box<dyn Error>> {
-        let parent = self.list_cwd_child_names()?;
-        if parent.len() == 0 {
-            return Err(app_error("No children"));
+    pub fn bookmarks_mut(&mut self) -> &mut Bookmarks {
+        &mut self.bookmarks
+    }
+
+    pub fn jumps_mut(&mut self) -> &mut JumpDb {
+        &mut self.jumps
+    }
+
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    pub fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// Collects the jobs which finished since the last call, noting them in the journal
+    pub fn poll_jobs(&mut self) -> Vec<Outcome> {
+        let mut finished = self.jobs.poll();
+        for outcome in &mut finished {
+            if let Some((_, undo)) = self.reverting.filter(|(id, _)| *id == outcome.id) {
+                self.reverting = None;
+                let result = if undo { self.journal.finish_undo(&outcome.steps) } else { self.journal.finish_redo(&outcome.steps) };
+                match result {
+                    Ok(summary) => outcome.summary = Some(summary),
+                    Err(e) => outcome.errors.push(e),
+                }
+                continue;
+            }
+            let kind = match outcome.kind {
+                JobKind::Copy => OpKind::Copy,
+                JobKind::Move => OpKind::Move,
+                JobKind::Trash => OpKind::Trash,
+                JobKind::Delete | JobKind::Attributes => continue,
+            };
+            // Overwritten targets were trashed first, undoing brings them back along with the rest
+            let (steps, replaced) = (std::mem::take(&mut outcome.steps), std::mem::take(&mut outcome.replaced));
+            if let Err(e) = self.journal.record_replacing(kind, steps, replaced) {
+                outcome.errors.push(format!("Could not save the journal: {}", e));
+            }
         }
-        if !self.child_is_folder(idx) {
-            return Err(app_error("Child is not a folder"));
+        if !finished.is_empty() {
+            self.selection.prune();
         }
-        if let Ok(child) = self.path_nth_child(&self.cwd, idx) {
-            self.cwd = child.clone();
+        finished
+    }
+
+    /// Renames entries within their directories, noting it in the journal
+    pub fn rename(&mut self, pairs: &[(PathBuf, PathBuf)]) -> Result<(), Box<dyn Error>> {
+        let result = rename::rename_all(pairs);
+        // Whatever made it is worth undoing, even when not everything did
+        let steps = pairs.iter()
+            .filter_map(|(from, to)| {
+                let stamp = Stamp::of(to)?;
+                Some(Step { from: from.clone(), to: to.clone(), stamp })
+            })
+            .filter(|step| result.is_ok() || step.from.symlink_metadata().is_err())
+            .collect();
+        self.journal.record(OpKind::Rename, steps)
+            .map_err(|e| app_error(&format!("Renamed, but could not save the journal: {}", e)))?;
+        self.selection.prune();
+        result.map_err(|e| app_error(&e))?;
+        Ok(())
+    }
+
+    /// Creates a new file or directory as typed in the prompt, noting it in the journal.
+    /// Returns the new entry.
+    pub fn create(&mut self, prompt: &CreatePrompt) -> Result<PathBuf, Box<dyn Error>> {
+        let (path, top) = prompt.create(&self.cwd).map_err(|e| app_error(&e))?;
+        if let Some(stamp) = Stamp::of(&top) {
+            self.journal.record(OpKind::Create, vec![Step { from: top.clone(), to: top, stamp }])
+                .map_err(|e| app_error(&format!("Created, but could not save the journal: {}", e)))?;
         }
-        return Ok(parent);
+        Ok(path)
     }
 
-    pub fn pop_last_visited_idx(&mut self) -> Option<usize> {
-        if let Some(last_item) = self.history.pop() {
-            if let Ok(dir) = self.cwd.as_path().read_dir() {
-                for (i, item) in dir.enumerate() {
-                    if let Ok(item) = item {
-                        if item.path() == last_item {
-                            return Some(i);
-                        }
-                    }
+    /// Links each of `sources` into `dir` under the same name, noting it in the journal.
+    /// Returns the links.
+    pub fn link(&mut self, kind: LinkKind, sources: &[PathBuf], dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut links = vec![];
+        let mut result = Ok(());
+        for source in sources {
+            match link::create_link(kind, source, dir) {
+                Ok(link) => links.push(link),
+                Err(e) => {
+                    result = Err(app_error(&format!("{}: {}", dir.join(source.file_name().unwrap_or_default()).display(), e)));
+                    break;
                 }
             }
         }
-        self.history = vec![];
-        None
+        let steps = links.iter()
+            .filter_map(|link| Some(Step { from: link.clone(), to: link.clone(), stamp: Stamp::of(link)? }))
+            .collect();
+        self.journal.record(OpKind::Create, steps)
+            .map_err(|e| app_error(&format!("Linked, but could not save the journal: {}", e)))?;
+        result?;
+        Ok(links)
     }
 
-    pub fn current_path(&self) -> &Path {
-        return self.cwd.as_path();
-    }
-
-    pub fn read_child_file(&self, idx: usize) -> Option<Vec<String>> {
-        use std::fs::File;
-        use std::io::{self, Read, BufRead, Seek, SeekFrom};
-        use content_inspector::{inspect, ContentType};
-        use itertools::Itertools;
-
-        // First, this hunk of mess determines if a file is text, so it can be displayed on-screen
-        // based on the first 512 bytes of it.
-        // Then, it seeks to the start of the file and reads 20 lines and sends it off for displaying
-
-        if let Ok(mut curr) = self.cwd.as_path().read_dir() {
-            if let Some(Ok(child)) = curr.nth(idx) {
-                let child_path = child.path();
-                if let Ok(mut handle) = File::open(child_path) {
-                    let buf: &mut [u8] = &mut [0; 512];
-                    if let Ok(_) = handle.read(buf) {
-                        handle.seek(SeekFrom::Start(0)).expect("Should be able to seek to the start of a stream");
-                        if inspect(buf) != ContentType::BINARY {
-                            let mut s = vec!();
-                            for lines in &io::BufReader::new(handle).lines().chunks(20) {
-                                for (_, line) in lines.enumerate() {
-                                    if let Ok(line) = line {
-                                        s.push(line);
-                                    }
-                                }
-                            }
-                            return Some(s);
-                        }
-                    }
-                }
+    /// Reverts the last file operation, returning what was done
+    pub fn undo(&mut self) -> Result<String, Box<dyn Error>> {
+        if self.jobs.is_busy() {
+            return Err(app_error("Wait for the running jobs to finish first"));
+        }
+        let revert = self.journal.undo().map_err(|e| app_error(&e))?;
+        Ok(self.start_revert(revert, true))
+    }
+
+    /// Does the last undone file operation again, returning what was done
+    pub fn redo(&mut self) -> Result<String, Box<dyn Error>> {
+        if self.jobs.is_busy() {
+            return Err(app_error("Wait for the running jobs to finish first"));
+        }
+        let revert = self.journal.redo().map_err(|e| app_error(&e))?;
+        Ok(self.start_revert(revert, false))
+    }
+
+    /// Queues the copying or moving an undo or redo takes, if any
+    fn start_revert(&mut self, revert: Revert, undo: bool) -> String {
+        match revert {
+            Revert::Done(message) => {
+                self.selection.prune();
+                message
+            }
+            Revert::Job { kind, pairs, message } => {
+                self.reverting = Some((self.jobs.push_pairs(kind, pairs), undo));
+                message
             }
         }
-        None
+    }
+
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    pub fn selection_mut(&mut self) -> &mut Selection {
+        &mut self.selection
+    }
+
+    /// What a command acts on: the selection if there is one, otherwise the given entry
+    pub fn targets(&self, entry: Option<&Entry>) -> Vec<PathBuf> {
+        if self.selection.is_empty() {
+            entry.map(|e| vec![e.path.clone()]).unwrap_or_default()
+        } else {
+            self.selection.paths()
+        }
+    }
+
+    /// Puts entries on the clipboard, to be copied or moved into another directory with `paste`
+    pub fn yank(&mut self, paths: Vec<PathBuf>, cut: bool) {
+        self.clipboard = Some(Clipboard { paths, cut });
+    }
+
+    /// Queues copying or moving the clipboard into the current directory.
+    /// Cut entries can only be pasted once.
+    pub fn paste(&mut self) -> Result<(), Box<dyn Error>> {
+        let clipboard = self.clipboard.as_ref().ok_or_else(|| app_error("Nothing to paste"))?;
+        let (kind, paths) = if clipboard.cut {
+            let paths = self.clipboard.take().map(|c| c.paths).unwrap_or_default();
+            (JobKind::Move, paths)
+        } else {
+            (JobKind::Copy, clipboard.paths.clone())
+        };
+        self.jobs.push(kind, paths, Some(self.cwd.clone()));
+        Ok(())
+    }
+
+    /// Queues moving the given entries into the trash
+    pub fn trash(&mut self, paths: Vec<PathBuf>) {
+        self.jobs.push(JobKind::Trash, paths, None);
+    }
+
+    /// Queues removing the given entries for good, recursively
+    pub fn delete(&mut self, paths: Vec<PathBuf>) {
+        self.jobs.push(JobKind::Delete, paths, None);
+    }
+
+    /// Writes everything that should outlive the session to disk
+    pub fn save_state(&mut self) -> Result<(), Box<dyn Error>> {
+        self.jumps.save()?;
+        self.cursors.save()?;
+        Ok(())
+    }
+
+    /// Remembers the entry selected in the current directory, to select it again when coming back
+    pub fn remember_selection(&mut self, selected: Option<&Path>) {
+        if let Some(selected) = selected {
+            self.cursors.remember(&self.cwd, selected);
+        }
+    }
+
+    /// Position of the entry last selected in the current directory within the given listing of it
+    pub fn remembered_idx(&self, entries: &[Entry]) -> Option<usize> {
+        self.cursors.position(&self.cwd, entries)
+    }
+
+    pub fn current_path(&self) -> &Path {
+        self.cwd.as_path()
     }
 }
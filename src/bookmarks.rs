@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use tui::widgets::ListState;
+use crate::{input::LineInput, storage};
+
+const BOOKMARKS_FILE: &str = "bookmarks";
+
+/// Named directories, kept in the config directory as `name<TAB>path` lines
+pub struct Bookmarks {
+    file: Option<PathBuf>,
+    marks: BTreeMap<String, PathBuf>,
+}
+impl Bookmarks {
+    pub fn load() -> Self {
+        let file = storage::config_dir().map(|d| d.join(BOOKMARKS_FILE));
+        let marks = file.as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .map(|s| parse(&s))
+            .unwrap_or_default();
+        Bookmarks { file, marks }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.marks.get(name).map(|p| p.as_path())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &PathBuf)> {
+        self.marks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+
+    /// Refuses paths the file can't hold, rather than saving them mangled
+    pub fn set(&mut self, name: &str, path: &Path) -> io::Result<()> {
+        if storable(path).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "paths with tabs, newlines or invalid UTF-8 can't be bookmarked"));
+        }
+        self.marks.insert(name.to_string(), path.to_path_buf());
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> io::Result<()> {
+        self.marks.remove(name);
+        self.save()
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        if let Some(path) = self.marks.remove(from) {
+            self.marks.insert(to.to_string(), path);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no config directory to save bookmarks to")),
+        };
+        let mut s = String::new();
+        for (name, path) in &self.marks {
+            if let Some(path) = storable(path) {
+                s.push_str(&format!("{}\t{}\n", name, path));
+            }
+        }
+        storage::write_atomic(file, &s)
+    }
+}
+
+/// The path as written to the file, if it survives being read back
+fn storable(path: &Path) -> Option<&str> {
+    path.to_str().filter(|p| !p.contains(['\t', '\n']))
+}
+
+fn parse(s: &str) -> BTreeMap<String, PathBuf> {
+    s.lines()
+        .filter_map(|line| {
+            let (name, path) = line.split_once('\t')?;
+            if name.is_empty() || path.is_empty() { return None }
+            Some((name.to_string(), PathBuf::from(path)))
+        })
+        .collect()
+}
+
+/// Names can't be empty or contain whitespace, since they are written to the file as is
+/// and passed on the command line as `@name`
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(char::is_whitespace)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditField {
+    Name,
+    Path,
+}
+
+/// The bookmark list popup, with an optional field being edited on the selected row
+pub struct BookmarkPopup {
+    pub state: ListState,
+    pub edit: Option<(EditField, LineInput)>,
+    pub error: Option<String>,
+}
+impl BookmarkPopup {
+    pub fn new(bookmarks: &Bookmarks) -> Self {
+        let mut state = ListState::default();
+        if !bookmarks.is_empty() {
+            state.select(Some(0));
+        }
+        BookmarkPopup { state, edit: None, error: None }
+    }
+
+    /// Name and path of the selected bookmark
+    pub fn selected<'a>(&self, bookmarks: &'a Bookmarks) -> Option<(&'a String, &'a PathBuf)> {
+        self.state.selected().and_then(|i| bookmarks.iter().nth(i))
+    }
+
+    pub fn next(&mut self, bookmarks: &Bookmarks) {
+        if bookmarks.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| (i + 1) % bookmarks.len());
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self, bookmarks: &Bookmarks) {
+        if bookmarks.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| if i == 0 { bookmarks.len() - 1 } else { i - 1 });
+        self.state.select(Some(i));
+    }
+
+    /// Keeps the selection in range after a bookmark was removed
+    pub fn clamp(&mut self, bookmarks: &Bookmarks) {
+        self.state.select(match self.state.selected() {
+            _ if bookmarks.is_empty() => None,
+            Some(i) => Some(i.min(bookmarks.len() - 1)),
+            None => Some(0),
+        });
+    }
+
+    pub fn start_edit(&mut self, bookmarks: &Bookmarks, field: EditField) {
+        if let Some((name, path)) = self.selected(bookmarks) {
+            let text = match field {
+                EditField::Name => name.clone(),
+                EditField::Path => path.to_string_lossy().into_owned(),
+            };
+            let mut input = LineInput::new();
+            input.set_text(&text);
+            self.edit = Some((field, input));
+            self.error = None;
+        }
+    }
+
+    /// Applies the edited field, leaving the field open with an error if the value is invalid
+    pub fn commit_edit(&mut self, bookmarks: &mut Bookmarks) {
+        let (field, input) = match &self.edit {
+            Some(edit) => edit,
+            None => return,
+        };
+        let (name, path) = match self.selected(bookmarks) {
+            Some((name, path)) => (name.clone(), path.clone()),
+            None => return,
+        };
+        let value = input.text().trim();
+        let result = match field {
+            EditField::Name => {
+                if !valid_name(value) {
+                    self.error = Some("Names can't be empty or contain spaces".to_string());
+                    return;
+                }
+                if value != name && bookmarks.get(value).is_some() {
+                    self.error = Some(format!("There is already a bookmark named '{}'", value));
+                    return;
+                }
+                let value = value.to_string();
+                let result = bookmarks.rename(&name, &value);
+                if let Some(i) = bookmarks.iter().position(|(n, _)| *n == value) {
+                    self.state.select(Some(i));
+                }
+                result
+            }
+            EditField::Path => {
+                let new_path = PathBuf::from(value);
+                if !new_path.is_absolute() || !new_path.is_dir() {
+                    self.error = Some("Not an absolute path to a directory".to_string());
+                    return;
+                }
+                if new_path == path {
+                    Ok(())
+                } else {
+                    bookmarks.set(&name, &new_path)
+                }
+            }
+        };
+        self.edit = None;
+        self.error = result.err().map(|e| format!("Could not save bookmarks: {}", e));
+    }
+}
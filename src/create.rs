@@ -0,0 +1,121 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use chrono::Local;
+use crate::{app::PATH_SEAPARATOR, goto, input::LineInput, storage};
+
+/// Below the config directory, each file in it is a template for new files
+const TEMPLATES_DIR: &str = "templates";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CreateKind {
+    File,
+    Directory,
+}
+
+/// Names of the templates new files can be made from
+pub fn templates() -> Vec<String> {
+    let dir = match storage::config_dir() {
+        Some(dir) => dir.join(TEMPLATES_DIR),
+        None => return vec![],
+    };
+    let mut names: Vec<String> = fs::read_dir(dir).into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Fills in the placeholders of a template for the file at `path`: `{name}` is the file name
+/// without the extension, `{file}` the whole file name, `{dir}` the name of the directory it's
+/// in, `{date}` today's date and `{year}` the year. Other braces are left as they are.
+fn fill(template: &str, path: &Path) -> String {
+    let name = |p: Option<&Path>| p.and_then(|p| p.file_name()).map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let today = Local::now();
+    template
+        .replace("{name}", &name(path.file_stem().map(Path::new)))
+        .replace("{file}", &name(Some(path)))
+        .replace("{dir}", &name(path.parent()))
+        .replace("{date}", &today.format("%Y-%m-%d").to_string())
+        .replace("{year}", &today.format("%Y").to_string())
+}
+
+/// Typing the path of a new file or directory, relative to the current directory
+pub struct CreatePrompt {
+    pub kind: CreateKind,
+    pub input: LineInput,
+    templates: Vec<String>,
+    template: Option<usize>,
+    pub error: Option<String>,
+}
+impl CreatePrompt {
+    pub fn new(kind: CreateKind) -> Self {
+        let templates = if kind == CreateKind::File { templates() } else { vec![] };
+        CreatePrompt { kind, input: LineInput::new(), templates, template: None, error: None }
+    }
+
+    pub fn has_templates(&self) -> bool {
+        !self.templates.is_empty()
+    }
+
+    pub fn template(&self) -> Option<&str> {
+        self.template.map(|i| self.templates[i].as_str())
+    }
+
+    /// Goes to the next template, or back to an empty file after the last one
+    pub fn cycle_template(&mut self) {
+        self.template = match self.template {
+            None if self.has_templates() => Some(0),
+            Some(i) if i + 1 < self.templates.len() => Some(i + 1),
+            _ => None,
+        };
+    }
+
+    /// Creates the entry along with the missing directories leading to it.
+    /// A trailing separator makes a directory even when asked for a file.
+    /// Returns the new entry and the topmost of the created ones.
+    pub fn create(&self, cwd: &Path) -> Result<(PathBuf, PathBuf), String> {
+        let text = self.input.text().trim();
+        let kind = if text.ends_with(PATH_SEAPARATOR) || text.ends_with('/') { CreateKind::Directory } else { self.kind };
+        let path = goto::resolve(text, cwd)?;
+        if path.symlink_metadata().is_ok() {
+            return Err(format!("{} already exists", path.display()));
+        }
+        let parent = path.parent().ok_or("Can't create the root directory")?;
+        let top = path.ancestors()
+            .take_while(|p| p.symlink_metadata().is_err())
+            .last()
+            .unwrap_or(&path)
+            .to_path_buf();
+        let contents = match self.template() {
+            Some(name) if kind == CreateKind::File => {
+                let file = storage::config_dir().unwrap_or_default().join(TEMPLATES_DIR).join(name);
+                let template = fs::read_to_string(&file).map_err(|e| format!("Can't read the template {}: {}", name, e))?;
+                fill(&template, &path)
+            }
+            _ => String::new(),
+        };
+        fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        let result = match kind {
+            CreateKind::Directory => fs::create_dir(&path),
+            CreateKind::File => fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .and_then(|mut f| f.write_all(contents.as_bytes())),
+        };
+        if let Err(e) = result {
+            // Nothing else could be in the directories made just now
+            if top != path {
+                fs::remove_dir_all(&top).ok();
+            }
+            return Err(format!("{}: {}", path.display(), e));
+        }
+        Ok((path, top))
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use crate::{entry::Entry, storage};
+
+const CURSORS_FILE: &str = "cursors";
+/// Directories beyond this many, least recently left first, are forgotten
+const MAX_DIRS: usize = 500;
+
+struct Cursor {
+    dir: PathBuf,
+    /// Name of the selected entry, so it's found again after entries were added or removed
+    name: OsString,
+}
+
+/// The entry last selected in every directory, kept between sessions
+pub struct Cursors {
+    file: Option<PathBuf>,
+    /// Least recently updated first
+    cursors: Vec<Cursor>,
+    dirty: bool,
+}
+impl Cursors {
+    pub fn load() -> Self {
+        let file = storage::data_dir().map(|d| d.join(CURSORS_FILE));
+        let cursors = file.as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .map(|s| parse(&s))
+            .unwrap_or_default();
+        Cursors { file, cursors, dirty: false }
+    }
+
+    /// Remembers `selected` as the selection of `dir`
+    pub fn remember(&mut self, dir: &Path, selected: &Path) {
+        let name = match selected.file_name() {
+            Some(name) => name.to_os_string(),
+            None => return,
+        };
+        self.cursors.retain(|c| c.dir != dir);
+        self.cursors.push(Cursor { dir: dir.to_path_buf(), name });
+        if self.cursors.len() > MAX_DIRS {
+            self.cursors.remove(0);
+        }
+        self.dirty = true;
+    }
+
+    /// Position of the entry last selected in `dir` within its listing
+    pub fn position(&self, dir: &Path, entries: &[Entry]) -> Option<usize> {
+        let cursor = self.cursors.iter().rev().find(|c| c.dir == dir)?;
+        entries.iter().position(|e| e.name == cursor.name)
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let file = match &self.file {
+            Some(file) if self.dirty => file,
+            _ => return Ok(()),
+        };
+        let mut s = String::new();
+        for cursor in &self.cursors {
+            let dir = cursor.dir.to_string_lossy();
+            let name = cursor.name.to_string_lossy();
+            if dir.contains(['\t', '\n']) || name.contains(['\t', '\n']) { continue }
+            s.push_str(&format!("{}\t{}\n", dir, name));
+        }
+        storage::write_atomic(file, &s)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn parse(s: &str) -> Vec<Cursor> {
+    s.lines()
+        .filter_map(|line| {
+            let (dir, name) = line.split_once('\t')?;
+            Some(Cursor { dir: PathBuf::from(dir), name: OsString::from(name) })
+        })
+        .collect()
+}
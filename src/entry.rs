@@ -0,0 +1,82 @@
+use std::{
+    ffi::OsString,
+    fs::{self, DirEntry, Metadata},
+    io,
+    path::PathBuf,
+    time::SystemTime,
+};
+use crate::app::PATH_SEAPARATOR;
+
+/// A single directory entry, captured once per listing.
+///
+/// Everything the ui needs to render, sort or preview an item is read here,
+/// so all the columns work off the same snapshot of a directory.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: OsString,
+    /// Whether the entry is a directory or a symlink pointing to one
+    pub is_dir: bool,
+    pub link_target: Option<PathBuf>,
+    /// A symlink pointing to nothing, its other fields describe the link itself
+    pub broken: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// Status change time on unix, creation time on windows
+    pub changed: Option<SystemTime>,
+}
+impl Entry {
+    pub fn from_dir_entry(item: &DirEntry) -> io::Result<Self> {
+        Self::with_name(item.path(), item.file_name())
+    }
+
+    fn with_name(path: PathBuf, name: OsString) -> io::Result<Self> {
+        let link_meta = fs::symlink_metadata(&path)?;
+        let (meta, link_target, broken) = if link_meta.file_type().is_symlink() {
+            let link_target = fs::read_link(&path).ok();
+            match fs::metadata(&path) {
+                Ok(meta) => (meta, link_target, false),
+                Err(_) => (link_meta, link_target, true),
+            }
+        } else {
+            (link_meta, None, false)
+        };
+        Ok(Entry {
+            name,
+            is_dir: meta.is_dir(),
+            link_target,
+            broken,
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            changed: changed(&meta),
+            path,
+        })
+    }
+
+    pub fn name_lossy(&self) -> String {
+        self.name.to_string_lossy().into_owned()
+    }
+
+    /// Name as shown in the lists, directories get a trailing separator
+    pub fn display_name(&self) -> String {
+        let name = self.name_lossy();
+        if self.is_dir && !name.ends_with(PATH_SEAPARATOR) {
+            name + PATH_SEAPARATOR
+        } else {
+            name
+        }
+    }
+}
+
+/// Status change time on unix, creation time on windows
+#[cfg(unix)]
+pub fn changed(meta: &Metadata) -> Option<SystemTime> {
+    use std::{os::unix::fs::MetadataExt, time::{Duration, UNIX_EPOCH}};
+    if meta.ctime() < 0 { return None }
+    Some(UNIX_EPOCH + Duration::new(meta.ctime() as u64, meta.ctime_nsec() as u32))
+}
+
+#[cfg(windows)]
+pub fn changed(meta: &Metadata) -> Option<SystemTime> {
+    meta.created().ok()
+}
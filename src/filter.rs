@@ -0,0 +1,171 @@
+use crate::input::LineInput;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    Substring,
+    Glob,
+    Fuzzy,
+}
+impl MatchMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchMode::Substring => "substring",
+            MatchMode::Glob => "glob",
+            MatchMode::Fuzzy => "fuzzy",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            MatchMode::Substring => MatchMode::Glob,
+            MatchMode::Glob => MatchMode::Fuzzy,
+            MatchMode::Fuzzy => MatchMode::Substring,
+        }
+    }
+}
+
+/// Narrows a directory listing down to the names matching the typed query
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub mode: MatchMode,
+    pub input: LineInput,
+}
+impl Filter {
+    pub fn new() -> Self {
+        Filter {
+            mode: MatchMode::Substring,
+            input: LineInput::new(),
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        self.input.text()
+    }
+
+    /// Positions of the chars in `name` matched by the query, `None` if it doesn't match.
+    /// Matching ignores case unless the query contains an uppercase letter.
+    pub fn matches(&self, name: &str) -> Option<Vec<usize>> {
+        let query = self.query();
+        if query.is_empty() {
+            return Some(vec![]);
+        }
+        let (query, name) = fold_case(query, name);
+        match self.mode {
+            MatchMode::Substring => substring_match(&query, &name),
+            MatchMode::Glob => glob_match(&query, &name),
+            MatchMode::Fuzzy => fuzzy_match(&query, &name).map(|(_, positions)| positions),
+        }
+    }
+}
+
+/// Lowercases both strings unless the query has uppercase letters in it
+pub fn fold_case(query: &str, name: &str) -> (String, String) {
+    if query.chars().any(|c| c.is_uppercase()) {
+        (query.to_string(), name.to_string())
+    } else {
+        (query.to_lowercase(), name.to_lowercase())
+    }
+}
+
+pub fn substring_match(query: &str, name: &str) -> Option<Vec<usize>> {
+    let byte_idx = name.find(query)?;
+    let start = name[..byte_idx].chars().count();
+    Some((start..start + query.chars().count()).collect())
+}
+
+/// Matches the whole name against a shell style pattern with `*`, `?` and `[...]` classes.
+/// When the rest stops matching, only the last `*` takes one more char, which keeps it linear
+/// in the length of both rather than trying every split of every star.
+pub fn glob_match(pattern: &str, name: &str) -> Option<Vec<usize>> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut positions = vec![];
+    // Pattern index after the last star, the name index it took chars up to and the positions then
+    let mut star: Option<(usize, usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n, positions.len()));
+                continue;
+            }
+            Some(&c) => {
+                let (matched, consumed) = match c {
+                    '?' => (true, 1),
+                    '[' => class_match(&pattern[p..], name[n]).unwrap_or((c == name[n], 1)),
+                    _ => (c == name[n], 1),
+                };
+                if matched {
+                    positions.push(n);
+                    p += consumed;
+                    n += 1;
+                    continue;
+                }
+            }
+            None => {}
+        }
+        let (after, taken, len) = star?;
+        star = Some((after, taken + 1, len));
+        positions.truncate(len);
+        p = after;
+        n = taken + 1;
+    }
+    while pattern.get(p) == Some(&'*') {
+        p += 1;
+    }
+    if p == pattern.len() { Some(positions) } else { None }
+}
+
+/// Checks `c` against the `[...]` class at the start of the pattern,
+/// returns whether it matched and the length of the class, `None` if the class isn't closed
+fn class_match(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated { i += 1 }
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&p) = pattern.get(i) {
+        if p == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|e| *e != ']') {
+            let end = pattern[i + 2];
+            if p <= c && c <= end { matched = true }
+            i += 3;
+        } else {
+            if p == c { matched = true }
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Matches the query as a subsequence of the name, returning a score (higher is better)
+/// and the matched positions. Consecutive runs and matches at word starts score higher.
+pub fn fuzzy_match(query: &str, name: &str) -> Option<(i64, Vec<usize>)> {
+    let name: Vec<char> = name.chars().collect();
+    let mut positions: Vec<usize> = Vec::with_capacity(query.len());
+    let mut score: i64 = 0;
+    let mut from = 0;
+    for q in query.chars() {
+        let found = (from..name.len()).find(|&i| name[i] == q)?;
+        score += 1;
+        if found == 0 || !name[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if let Some(&last) = positions.last() {
+            if found == last + 1 {
+                score += 5;
+            } else {
+                score -= (found - last - 1).min(5) as i64;
+            }
+        }
+        positions.push(found);
+        from = found + 1;
+    }
+    // Prefer shorter names for the same matches
+    score -= (name.len() / 8) as i64;
+    Some((score, positions))
+}
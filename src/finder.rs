@@ -0,0 +1,217 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+use tui::widgets::ListState;
+use crate::{
+    app::{is_hidden, PATH_SEAPARATOR},
+    filter::{fold_case, fuzzy_match},
+    input::LineInput,
+};
+
+/// How deep below the root the walker descends
+const MAX_DEPTH: usize = 16;
+/// The walker stops after this many entries, so huge trees stay responsive
+const MAX_ENTRIES: usize = 200_000;
+/// Entries are sent to the ui in batches of this size
+const BATCH_SIZE: usize = 512;
+
+enum Walk {
+    Found(Vec<Found>),
+    Done { truncated: bool },
+}
+
+pub struct Found {
+    pub path: PathBuf,
+    /// Path relative to the search root, as displayed and matched
+    pub display: String,
+    pub is_dir: bool,
+}
+
+/// Recursive fuzzy search below a directory, fed by a walker thread
+pub struct Finder {
+    root: PathBuf,
+    pub input: LineInput,
+    found: Vec<Found>,
+    /// Indices into `found` that match the query, best first, with the matched positions
+    ranked: Vec<(usize, Vec<usize>)>,
+    pub state: ListState,
+    rx: Receiver<Walk>,
+    cancel: Arc<AtomicBool>,
+    done: bool,
+    truncated: bool,
+}
+impl Finder {
+    pub fn spawn(root: &Path, show_hidden: bool, dirs_only: bool) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let walker_cancel = cancel.clone();
+        let walker_root = root.to_path_buf();
+        thread::spawn(move || walk(walker_root, show_hidden, dirs_only, tx, walker_cancel));
+        Finder {
+            root: root.to_path_buf(),
+            input: LineInput::new(),
+            found: vec![],
+            ranked: vec![],
+            state: ListState::default(),
+            rx,
+            cancel,
+            done: false,
+            truncated: false,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn total(&self) -> usize {
+        self.found.len()
+    }
+
+    /// Picks up whatever the walker found since the last call
+    pub fn poll(&mut self) {
+        let before = self.found.len();
+        while let Ok(msg) = self.rx.try_recv() {
+            match msg {
+                Walk::Found(batch) => self.found.extend(batch),
+                Walk::Done { truncated } => {
+                    self.done = true;
+                    self.truncated = truncated;
+                }
+            }
+        }
+        if self.found.len() != before {
+            self.rank();
+        }
+    }
+
+    /// Re-scores every entry against the query, call after the input changed
+    pub fn rank(&mut self) {
+        let selected = self.selected().map(|f| f.path.clone());
+        let query = self.input.text();
+        let mut scored: Vec<(i64, usize, Vec<usize>)> = self.found.iter()
+            .enumerate()
+            .filter_map(|(i, found)| {
+                if query.is_empty() {
+                    return Some((0, i, vec![]));
+                }
+                let (query, display) = fold_case(query, &found.display);
+                fuzzy_match(&query, &display).map(|(score, positions)| (score, i, positions))
+            })
+            .collect();
+        if !query.is_empty() {
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        }
+        self.ranked = scored.into_iter().map(|(_, i, positions)| (i, positions)).collect();
+
+        let idx = selected
+            .filter(|_| query.is_empty())
+            .and_then(|p| self.ranked.iter().position(|(i, _)| self.found[*i].path == p))
+            .unwrap_or(0);
+        self.state.select(if self.ranked.is_empty() { None } else { Some(idx) });
+    }
+
+    /// The matching entries in ranked order, with the matched char positions
+    pub fn results(&self) -> impl Iterator<Item = (&Found, &[usize])> {
+        self.ranked.iter().map(move |(i, positions)| (&self.found[*i], positions.as_slice()))
+    }
+
+    pub fn result_count(&self) -> usize {
+        self.ranked.len()
+    }
+
+    pub fn selected(&self) -> Option<&Found> {
+        self.state.selected()
+            .and_then(|i| self.ranked.get(i))
+            .map(|(i, _)| &self.found[*i])
+    }
+
+    pub fn next(&mut self) {
+        if self.ranked.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| (i + 1) % self.ranked.len());
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self) {
+        if self.ranked.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| if i == 0 { self.ranked.len() - 1 } else { i - 1 });
+        self.state.select(Some(i));
+    }
+}
+impl Drop for Finder {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Breadth first walk, so the shallow results which are likely wanted show up first
+fn walk(root: PathBuf, show_hidden: bool, dirs_only: bool, tx: Sender<Walk>, cancel: Arc<AtomicBool>) {
+    let mut queue = VecDeque::new();
+    queue.push_back((root.clone(), 0));
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut count = 0;
+    let mut truncated = false;
+
+    'walk: while let Some((dir, depth)) = queue.pop_front() {
+        let items = match dir.read_dir() {
+            Ok(items) => items,
+            Err(_) => continue,
+        };
+        for item in items.flatten() {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let path = item.path();
+            if !show_hidden && is_hidden(&path) {
+                continue;
+            }
+            // Don't follow symlinks into directories, they may loop
+            let is_dir = item.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if is_dir {
+                if depth + 1 < MAX_DEPTH {
+                    queue.push_back((path.clone(), depth + 1));
+                } else {
+                    truncated = true;
+                }
+            }
+            if dirs_only && !is_dir {
+                continue;
+            }
+            let mut display = path.strip_prefix(&root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+            if is_dir {
+                display.push_str(PATH_SEAPARATOR);
+            }
+            batch.push(Found { path, display, is_dir });
+            count += 1;
+            if count >= MAX_ENTRIES {
+                truncated = true;
+                break 'walk;
+            }
+            if batch.len() >= BATCH_SIZE && tx.send(Walk::Found(std::mem::take(&mut batch))).is_err() {
+                return;
+            }
+        }
+    }
+    if !batch.is_empty() && tx.send(Walk::Found(batch)).is_err() {
+        return;
+    }
+    tx.send(Walk::Done { truncated }).ok();
+}
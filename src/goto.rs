@@ -0,0 +1,202 @@
+use std::{
+    env,
+    path::{Component, Path, PathBuf},
+};
+use tui::widgets::ListState;
+use crate::{app::{is_hidden, PATH_SEAPARATOR}, input::LineInput};
+
+/// How many completions the dropdown shows at most
+const MAX_CANDIDATES: usize = 200;
+
+/// Expands a leading `~` and any `$VAR` or `${VAR}` in a typed path
+pub fn expand(input: &str) -> Result<String, String> {
+    let mut s = String::new();
+    let mut chars = input.chars().peekable();
+    if input == "~" || input.starts_with("~/") || input.starts_with(&format!("~{}", PATH_SEAPARATOR)) {
+        let home = env::var("HOME")
+            .or_else(|_| env::var("USERPROFILE"))
+            .map_err(|_| "Can't expand ~, HOME is not set".to_string())?;
+        s.push_str(&home);
+        chars.next();
+    }
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            s.push(c);
+            continue;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced { chars.next(); }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if braced && c == '}' {
+                chars.next();
+                break;
+            }
+            if !(braced || c.is_alphanumeric() || c == '_') { break }
+            name.push(c);
+            chars.next();
+        }
+        if name.is_empty() {
+            s.push('$');
+            continue;
+        }
+        let value = env::var(&name).map_err(|_| format!("${} is not set", name))?;
+        s.push_str(&value);
+    }
+    Ok(s)
+}
+
+/// Turns typed text into an absolute path, relative ones are taken from `cwd`
+pub fn resolve(input: &str, cwd: &Path) -> Result<PathBuf, String> {
+    let expanded = expand(input.trim())?;
+    if expanded.is_empty() {
+        return Err("No path given".to_string());
+    }
+    Ok(normalize(&cwd.join(expanded)))
+}
+
+/// Drops `.` and resolves `..` components lexically, the way a shell's `cd` does
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push(component);
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// The `:` prompt for typing a path to go to
+pub struct GotoPrompt {
+    pub input: LineInput,
+    /// Names in the directory being typed, matching the last segment
+    candidates: Vec<String>,
+    pub state: ListState,
+    /// Set while cycling through the candidates with Tab,
+    /// holds the input the candidates were listed for
+    cycling: Option<String>,
+    pub error: Option<String>,
+}
+impl GotoPrompt {
+    pub fn new() -> Self {
+        GotoPrompt {
+            input: LineInput::new(),
+            candidates: vec![],
+            state: ListState::default(),
+            cycling: None,
+            error: None,
+        }
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// Splits the input into the directory part, ending in a separator, and the segment being typed
+    fn split_input(text: &str) -> (&str, &str) {
+        let idx = text.rfind(|c| c == '/' || PATH_SEAPARATOR.contains(c))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        text.split_at(idx)
+    }
+
+    /// Lists the completions for the current input, call after it changed
+    pub fn update(&mut self, cwd: &Path, show_hidden: bool) {
+        self.cycling = None;
+        self.error = None;
+        self.state.select(None);
+        self.candidates = vec![];
+        let (dir, prefix) = Self::split_input(self.input.text());
+        let dir = if dir.is_empty() {
+            cwd.to_path_buf()
+        } else {
+            match resolve(dir, cwd) {
+                Ok(dir) => dir,
+                Err(_) => return,
+            }
+        };
+        let items = match dir.read_dir() {
+            Ok(items) => items,
+            Err(_) => return,
+        };
+        let show_hidden = show_hidden || prefix.starts_with('.');
+        for item in items.flatten() {
+            let path = item.path();
+            if !show_hidden && is_hidden(&path) { continue }
+            let mut name = item.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) { continue }
+            if path.is_dir() {
+                name.push_str(PATH_SEAPARATOR);
+            }
+            self.candidates.push(name);
+        }
+        self.candidates.sort();
+        self.candidates.truncate(MAX_CANDIDATES);
+    }
+
+    /// Tab completion: fills in a single match or the common prefix of several,
+    /// then cycles through them on further presses
+    pub fn complete(&mut self, cwd: &Path, show_hidden: bool) {
+        if self.cycling.is_some() {
+            self.cycle(1);
+            return;
+        }
+        if self.candidates.is_empty() {
+            return;
+        }
+        let (dir, prefix) = Self::split_input(self.input.text());
+        let common = common_prefix(&self.candidates);
+        if self.candidates.len() == 1 || common.len() > prefix.len() {
+            let text = format!("{}{}", dir, common);
+            self.input.set_text(&text);
+            self.update(cwd, show_hidden);
+        } else {
+            self.cycle(1);
+        }
+    }
+
+    /// Moves through the candidates, putting the selected one into the input
+    pub fn cycle(&mut self, step: isize) {
+        if self.candidates.is_empty() {
+            return;
+        }
+        let base = match &self.cycling {
+            Some(base) => base.clone(),
+            None => self.input.text().to_string(),
+        };
+        let len = self.candidates.len() as isize;
+        let i = match self.state.selected() {
+            Some(i) => (i as isize + step).rem_euclid(len),
+            None if step < 0 => len - 1,
+            None => 0,
+        } as usize;
+        self.state.select(Some(i));
+        let (dir, _) = Self::split_input(&base);
+        let text = format!("{}{}", dir, self.candidates[i]);
+        self.input.set_text(&text);
+        self.cycling = Some(base);
+    }
+}
+
+fn common_prefix(names: &[String]) -> String {
+    let first = match names.first() {
+        Some(first) => first,
+        None => return String::new(),
+    };
+    let mut len = first.len();
+    for name in &names[1..] {
+        len = first.char_indices()
+            .zip(name.chars())
+            .take_while(|((i, a), b)| *i < len && a == b)
+            .last()
+            .map(|((i, a), _)| i + a.len_utf8())
+            .unwrap_or(0);
+    }
+    first[..len].to_string()
+}
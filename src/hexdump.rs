@@ -0,0 +1,47 @@
+use tui::{
+    style::{Color, Style},
+    text::{Span, Spans},
+};
+
+/// Bytes on a row of the pager, the preview takes as many as fit its width
+pub const BYTES_PER_ROW: usize = 16;
+
+/// Hex digits of the offsets into a file of `size` bytes
+pub fn offset_digits(size: u64) -> usize {
+    format!("{:x}", size.saturating_sub(1)).len().max(4)
+}
+
+/// How many bytes a row of `cols` columns has room for, in groups of four
+pub fn bytes_per_row(cols: usize, digits: usize) -> usize {
+    // The offset and a gap, then three columns for each byte in hex and one as text
+    let fit = cols.saturating_sub(digits + 3) / 4;
+    (fit / 4 * 4).clamp(4, BYTES_PER_ROW)
+}
+
+/// Nul bytes are dim, printable ones plain and the rest stand out
+fn style(byte: u8) -> Style {
+    match byte {
+        0 => Style::default().fg(Color::DarkGray),
+        b if b.is_ascii_graphic() || b == b' ' => Style::default(),
+        b if b.is_ascii_whitespace() => Style::default().fg(Color::Green),
+        _ => Style::default().fg(Color::Yellow),
+    }
+}
+
+/// A row of a classic hex dump: the offset, `width` bytes in hex, and the same bytes as text
+pub fn row(offset: u64, bytes: &[u8], width: usize, digits: usize) -> Spans<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut spans = vec![Span::styled(format!("{:0w$x}  ", offset, w = digits), dim)];
+    for i in 0..width {
+        match bytes.get(i) {
+            Some(&b) => spans.push(Span::styled(format!("{:02x} ", b), style(b))),
+            None => spans.push(Span::raw("   ")),
+        }
+    }
+    spans.push(Span::raw(" "));
+    for &b in bytes {
+        let c = if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' };
+        spans.push(Span::styled(c.to_string(), style(b)));
+    }
+    Spans::from(spans)
+}
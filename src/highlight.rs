@@ -0,0 +1,80 @@
+use std::{path::Path, sync::LazyLock};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Theme, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+};
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+/// Files larger than this are previewed as plain text, highlighting them would stall the ui
+const MAX_HIGHLIGHT_BYTES: u64 = 1024 * 1024;
+/// Only this many lines from the top are highlighted, the rest is left plain
+const MAX_HIGHLIGHT_LINES: usize = 2000;
+/// Longer lines are mostly minified code or data, the parser is slow on them
+const MAX_LINE_LENGTH: usize = 2000;
+const THEME: &str = "base16-ocean.dark";
+
+/// The bundled syntaxes and theme, loaded the first time a file is previewed
+static HIGHLIGHTING: LazyLock<(SyntaxSet, Theme)> = LazyLock::new(|| {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let mut themes = ThemeSet::load_defaults();
+    let theme = themes.themes.remove(THEME).unwrap_or_default();
+    (syntaxes, theme)
+});
+
+/// The syntax for a file, by its extension or name, or else by a shebang or mode line
+fn syntax_for<'a>(syntaxes: &'a SyntaxSet, path: &Path, first_line: &str) -> Option<&'a SyntaxReference> {
+    let name = path.file_name()?.to_string_lossy();
+    let extension = path.extension().map(|e| e.to_string_lossy());
+    extension.and_then(|e| syntaxes.find_syntax_by_extension(&e))
+        // Files like Makefile or .bashrc are known by their whole name
+        .or_else(|| syntaxes.find_syntax_by_extension(&name))
+        .or_else(|| syntaxes.find_syntax_by_first_line(first_line))
+        .filter(|s| s.name != "Plain Text")
+}
+
+/// Colors the preview of a file, `None` when its type is unknown or it's too large
+pub fn highlight(path: &Path, size: u64, lines: &[String]) -> Option<Vec<Spans<'static>>> {
+    if size > MAX_HIGHLIGHT_BYTES {
+        return None;
+    }
+    let (syntaxes, theme) = &*HIGHLIGHTING;
+    let syntax = syntax_for(syntaxes, path, lines.first().map(String::as_str).unwrap_or_default())?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut result = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        if i >= MAX_HIGHLIGHT_LINES || line.len() > MAX_LINE_LENGTH {
+            // The parser state is lost from here on
+            result.extend(lines[i..].iter().map(|l| Spans::from(l.clone())));
+            break;
+        }
+        // The syntaxes expect the line ending
+        let line = format!("{}\n", line);
+        let regions = highlighter.highlight_line(&line, syntaxes).ok()?;
+        let spans: Vec<Span<'static>> = regions.into_iter()
+            .map(|(style, text)| Span::styled(text.trim_end_matches('\n').to_string(), convert(style)))
+            .filter(|s| !s.content.is_empty())
+            .collect();
+        result.push(Spans::from(spans));
+    }
+    Some(result)
+}
+
+/// Takes the foreground and font style of a highlighted region, the background is left to the terminal
+fn convert(style: syntect::highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut result = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        result = result.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        result = result.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        result = result.add_modifier(Modifier::UNDERLINED);
+    }
+    result
+}
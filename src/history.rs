@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+use tui::widgets::ListState;
+
+/// Older visits are dropped past this many
+const MAX_VISITS: usize = 1000;
+
+pub struct Visit {
+    pub path: PathBuf,
+    /// The entry that was selected when the directory was left
+    pub selected: Option<PathBuf>,
+}
+
+/// Browser style back and forward history of the directories visited this session
+pub struct History {
+    visits: Vec<Visit>,
+    /// Index of the visit we are at
+    pos: usize,
+}
+impl History {
+    pub fn new(start: &Path) -> Self {
+        History {
+            visits: vec![Visit { path: start.to_path_buf(), selected: None }],
+            pos: 0,
+        }
+    }
+
+    /// Records moving on to `path`, dropping everything we could have gone forward to
+    pub fn push(&mut self, selected: Option<&Path>, path: &Path) {
+        self.remember_selection(selected);
+        self.visits.truncate(self.pos + 1);
+        self.visits.push(Visit { path: path.to_path_buf(), selected: None });
+        if self.visits.len() > MAX_VISITS {
+            self.visits.remove(0);
+        }
+        self.pos = self.visits.len() - 1;
+    }
+
+    fn remember_selection(&mut self, selected: Option<&Path>) {
+        if let Some(visit) = self.visits.get_mut(self.pos) {
+            visit.selected = selected.map(|p| p.to_path_buf());
+        }
+    }
+
+    /// Steps back to the closest directory which still exists
+    pub fn back(&mut self, selected: Option<&Path>) -> Option<&Visit> {
+        let pos = (0..self.pos).rev().find(|&i| self.visits[i].path.is_dir())?;
+        self.remember_selection(selected);
+        self.pos = pos;
+        self.visits.get(pos)
+    }
+
+    /// Steps forward to the closest directory which still exists
+    pub fn forward(&mut self, selected: Option<&Path>) -> Option<&Visit> {
+        let pos = (self.pos + 1..self.visits.len()).find(|&i| self.visits[i].path.is_dir())?;
+        self.remember_selection(selected);
+        self.pos = pos;
+        self.visits.get(pos)
+    }
+
+    /// The directory visited before the current one, for `cd -` style toggling
+    pub fn previous_dir(&self) -> Option<&Path> {
+        let current = &self.visits.get(self.pos)?.path;
+        self.visits[..self.pos].iter()
+            .rev()
+            .map(|v| &v.path)
+            .find(|p| *p != current && p.is_dir())
+            .map(|p| p.as_path())
+    }
+
+    /// Every visited directory once, most recent first
+    pub fn recent(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = vec![];
+        for visit in self.visits.iter().rev() {
+            if !dirs.contains(&visit.path) {
+                dirs.push(visit.path.clone());
+            }
+        }
+        dirs
+    }
+}
+
+/// Popup listing the recently visited directories
+pub struct HistoryPopup {
+    pub dirs: Vec<PathBuf>,
+    pub state: ListState,
+}
+impl HistoryPopup {
+    pub fn new(history: &History) -> Self {
+        let dirs = history.recent();
+        let mut state = ListState::default();
+        // The first one is where we are now, so offer the one before by default
+        state.select(match dirs.len() {
+            0 => None,
+            1 => Some(0),
+            _ => Some(1),
+        });
+        HistoryPopup { dirs, state }
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.state.selected().and_then(|i| self.dirs.get(i)).map(|p| p.as_path())
+    }
+
+    pub fn next(&mut self) {
+        if self.dirs.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| (i + 1) % self.dirs.len());
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self) {
+        if self.dirs.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| if i == 0 { self.dirs.len() - 1 } else { i - 1 });
+        self.state.select(Some(i));
+    }
+}
@@ -0,0 +1,161 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Single line text field with a cursor, used by the prompts
+#[derive(Debug, Clone, Default)]
+pub struct LineInput {
+    text: String,
+    /// Cursor position in chars, not bytes
+    cursor: usize,
+    /// Selected range of chars, replaced by whatever is typed next
+    selection: Option<(usize, usize)>,
+}
+impl LineInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replaces the content, moving the cursor to the end
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.len();
+        self.selection = None;
+    }
+
+    /// Selects the chars from `from` up to `to`, with the cursor at the end of them
+    pub fn select(&mut self, from: usize, to: usize) {
+        let to = to.min(self.len());
+        self.selection = if from < to { Some((from, to)) } else { None };
+        self.cursor = to;
+    }
+
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        self.selection
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn byte_idx(&self, char_idx: usize) -> usize {
+        self.text.char_indices()
+            .nth(char_idx)
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| self.text.len())
+    }
+
+    fn insert(&mut self, c: char) {
+        let i = self.byte_idx(self.cursor);
+        self.text.insert(i, c);
+        self.cursor += 1;
+    }
+
+    /// Removes the chars between the two cursor positions
+    fn remove_range(&mut self, from: usize, to: usize) {
+        let (from, to) = (self.byte_idx(from), self.byte_idx(to));
+        self.text.replace_range(from..to, "");
+    }
+
+    fn word_start_before(&self, idx: usize) -> usize {
+        let chars: Vec<char> = self.text.chars().collect();
+        let mut i = idx;
+        while i > 0 && !is_word_char(chars[i - 1]) { i -= 1 }
+        while i > 0 && is_word_char(chars[i - 1]) { i -= 1 }
+        i
+    }
+
+    fn word_end_after(&self, idx: usize) -> usize {
+        let chars: Vec<char> = self.text.chars().collect();
+        let mut i = idx;
+        while i < chars.len() && !is_word_char(chars[i]) { i += 1 }
+        while i < chars.len() && is_word_char(chars[i]) { i += 1 }
+        i
+    }
+
+    /// Applies an editing key, returns whether the key was used
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        // Any key ends the selection, typing replaces it and moving collapses it
+        if let Some((from, to)) = self.selection.take() {
+            match key.code {
+                KeyCode::Char(_) if !ctrl && !alt => {
+                    self.remove_range(from, to);
+                    self.cursor = from;
+                }
+                KeyCode::Backspace | KeyCode::Delete if !ctrl && !alt => {
+                    self.remove_range(from, to);
+                    self.cursor = from;
+                    return true;
+                }
+                KeyCode::Left if !ctrl => {
+                    self.cursor = from;
+                    return true;
+                }
+                KeyCode::Right if !ctrl => {
+                    self.cursor = to;
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.len(),
+            KeyCode::Char('u') if ctrl => {
+                self.remove_range(0, self.cursor);
+                self.cursor = 0;
+            }
+            KeyCode::Char('k') if ctrl => {
+                self.remove_range(self.cursor, self.len());
+            }
+            KeyCode::Char('w') if ctrl => {
+                let start = self.word_start_before(self.cursor);
+                self.remove_range(start, self.cursor);
+                self.cursor = start;
+            }
+            KeyCode::Char('b') if alt => self.cursor = self.word_start_before(self.cursor),
+            KeyCode::Char('f') if alt => self.cursor = self.word_end_after(self.cursor),
+            KeyCode::Char('d') if alt => {
+                let end = self.word_end_after(self.cursor);
+                self.remove_range(self.cursor, end);
+            }
+            KeyCode::Char(_) if ctrl || alt => return false,
+            KeyCode::Char(c) => self.insert(c),
+            KeyCode::Backspace if ctrl || alt => {
+                let start = self.word_start_before(self.cursor);
+                self.remove_range(start, self.cursor);
+                self.cursor = start;
+            }
+            KeyCode::Backspace => {
+                if self.cursor == 0 { return true }
+                self.remove_range(self.cursor - 1, self.cursor);
+                self.cursor -= 1;
+            }
+            KeyCode::Delete => {
+                if self.cursor < self.len() {
+                    self.remove_range(self.cursor, self.cursor + 1);
+                }
+            }
+            KeyCode::Left if ctrl => self.cursor = self.word_start_before(self.cursor),
+            KeyCode::Right if ctrl => self.cursor = self.word_end_after(self.cursor),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.len(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -0,0 +1,800 @@
+use std::{
+    fs::{self, File, FileTimes},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+use crate::{journal::{Stamp, Step}, props::Change, trash};
+
+/// Files are copied in chunks of this size, checking for cancellation in between
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobKind {
+    Copy,
+    Move,
+    Trash,
+    Delete,
+    /// Permissions and ownership of a whole tree
+    Attributes,
+}
+impl JobKind {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            JobKind::Copy => "Copying",
+            JobKind::Move => "Moving",
+            JobKind::Trash => "Trashing",
+            JobKind::Delete => "Deleting",
+            JobKind::Attributes => "Changing",
+        }
+    }
+
+    pub fn past(&self) -> &'static str {
+        match self {
+            JobKind::Copy => "Copied",
+            JobKind::Move => "Moved",
+            JobKind::Trash => "Trashed",
+            JobKind::Delete => "Deleted",
+            JobKind::Attributes => "Changed",
+        }
+    }
+}
+
+/// What to do with an entry whose target already exists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Choice {
+    /// Replace the target, which goes to the trash
+    Overwrite,
+    Skip,
+    /// Put it next to the target as `name (2).ext`
+    Rename,
+    /// Overwrite only if the entry was modified later than the target
+    OverwriteIfNewer,
+    /// Check whether both have the same contents before deciding
+    Compare,
+}
+
+/// Size and modification time of one side of a conflict
+#[derive(Clone)]
+pub struct Side {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+impl Side {
+    fn of(path: &Path) -> Self {
+        let meta = fs::symlink_metadata(path).ok();
+        Side {
+            path: path.to_path_buf(),
+            is_dir: meta.as_ref().is_some_and(|m| m.is_dir()),
+            size: measure(path).1,
+            modified: meta.and_then(|m| m.modified().ok()),
+        }
+    }
+}
+
+/// An entry to copy or move whose target already exists, waiting for a choice
+#[derive(Clone)]
+pub struct Conflict {
+    pub kind: JobKind,
+    pub source: Side,
+    pub target: Side,
+    /// Whether both have the same contents, once compared
+    pub same: Option<bool>,
+}
+impl Conflict {
+    pub fn source_is_newer(&self) -> bool {
+        match (self.source.modified, self.target.modified) {
+            (Some(source), Some(target)) => source > target,
+            _ => false,
+        }
+    }
+}
+
+/// Entries yanked or cut, waiting to be pasted
+pub struct Clipboard {
+    pub paths: Vec<PathBuf>,
+    pub cut: bool,
+}
+
+struct Job {
+    id: u64,
+    kind: JobKind,
+    sources: Vec<PathBuf>,
+    /// Directory to copy or move into
+    dest: Option<PathBuf>,
+    /// Where each of the sources goes instead, as when undoing
+    targets: Vec<PathBuf>,
+    /// What to change about everything below the sources, for attribute jobs
+    change: Option<Change>,
+    cancel: Arc<AtomicBool>,
+}
+
+enum Update {
+    Started { id: u64, files: u64, bytes: u64 },
+    Progress { id: u64, files: u64, bytes: u64, current: PathBuf },
+    Conflict(Conflict),
+    Finished(Outcome),
+}
+
+/// A choice for the pending conflict, `all` applies it to the rest of the job
+struct Answer {
+    choice: Choice,
+    all: bool,
+}
+
+/// Summary of a job once the worker is done with it
+pub struct Outcome {
+    pub id: u64,
+    pub kind: JobKind,
+    pub files: u64,
+    pub errors: Vec<String>,
+    /// Entries left alone as their targets existed
+    pub skipped: u64,
+    pub cancelled: bool,
+    /// What was copied, moved or trashed where, for the journal
+    pub steps: Vec<Step>,
+    /// Targets which were overwritten, they went to the trash first
+    pub replaced: Vec<Step>,
+    /// What the job came to, when there's more to say than its kind
+    pub summary: Option<String>,
+}
+
+/// A queued or running job, as seen by the ui
+pub struct JobStatus {
+    pub id: u64,
+    pub kind: JobKind,
+    pub label: String,
+    pub started: bool,
+    pub total_files: u64,
+    pub total_bytes: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub current: Option<PathBuf>,
+    cancel: Arc<AtomicBool>,
+}
+impl JobStatus {
+    /// Progress from 0 to 1, by bytes where there are any
+    pub fn ratio(&self) -> f64 {
+        if self.total_bytes > 0 {
+            (self.bytes as f64 / self.total_bytes as f64).min(1.0)
+        } else if self.total_files > 0 {
+            (self.files as f64 / self.total_files as f64).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+/// Queue of file operations, carried out one after the other on a worker thread
+pub struct Jobs {
+    tx: Option<Sender<Job>>,
+    rx: Receiver<Update>,
+    answers: Sender<Answer>,
+    worker: Option<JoinHandle<()>>,
+    jobs: Vec<JobStatus>,
+    conflict: Option<Conflict>,
+    next_id: u64,
+}
+impl Jobs {
+    pub fn new() -> Self {
+        let (tx, jobs_rx) = mpsc::channel::<Job>();
+        let (updates_tx, rx) = mpsc::channel();
+        let (answers, answers_rx) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            for job in jobs_rx {
+                let outcome = run(&job, &updates_tx, &answers_rx);
+                if updates_tx.send(Update::Finished(outcome)).is_err() {
+                    return;
+                }
+            }
+        });
+        Jobs {
+            tx: Some(tx),
+            rx,
+            answers,
+            worker: Some(worker),
+            jobs: vec![],
+            conflict: None,
+            next_id: 0,
+        }
+    }
+
+    /// Queues a job, `dest` is the directory to copy or move into
+    pub fn push(&mut self, kind: JobKind, sources: Vec<PathBuf>, dest: Option<PathBuf>) {
+        self.queue(kind, sources, dest, vec![], None);
+    }
+
+    /// Queues copying or moving each entry to the exact path paired with it, returns the job's id.
+    /// Nothing is asked about existing targets.
+    pub fn push_pairs(&mut self, kind: JobKind, pairs: Vec<(PathBuf, PathBuf)>) -> u64 {
+        let (sources, targets) = pairs.into_iter().unzip();
+        self.queue(kind, sources, None, targets, None)
+    }
+
+    /// Queues changing permissions and ownership of `path` and everything below it
+    pub fn push_change(&mut self, path: PathBuf, change: Change) {
+        self.queue(JobKind::Attributes, vec![path], None, vec![], Some(change));
+    }
+
+    fn queue(&mut self, kind: JobKind, sources: Vec<PathBuf>, dest: Option<PathBuf>, targets: Vec<PathBuf>, change: Option<Change>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let label = match sources.as_slice() {
+            [single] => single.file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| single.display().to_string()),
+            all => format!("{} items", all.len()),
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.jobs.push(JobStatus {
+            id,
+            kind,
+            label,
+            started: false,
+            total_files: 0,
+            total_bytes: 0,
+            files: 0,
+            bytes: 0,
+            current: None,
+            cancel: cancel.clone(),
+        });
+        if let Some(tx) = &self.tx {
+            tx.send(Job { id, kind, sources, dest, targets, change, cancel }).ok();
+        }
+        id
+    }
+
+    pub fn statuses(&self) -> &[JobStatus] {
+        &self.jobs
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.jobs.is_empty()
+    }
+
+    /// Applies the progress reported by the worker, returning the jobs which finished since
+    pub fn poll(&mut self) -> Vec<Outcome> {
+        let mut finished = vec![];
+        while let Ok(update) = self.rx.try_recv() {
+            match update {
+                Update::Started { id, files, bytes } => {
+                    if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+                        job.started = true;
+                        job.total_files = files;
+                        job.total_bytes = bytes;
+                    }
+                }
+                Update::Progress { id, files, bytes, current } => {
+                    if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+                        job.files = files;
+                        job.bytes = bytes;
+                        job.current = Some(current);
+                    }
+                }
+                Update::Conflict(conflict) => self.conflict = Some(conflict),
+                Update::Finished(outcome) => {
+                    self.jobs.retain(|j| j.id != outcome.id);
+                    finished.push(outcome);
+                }
+            }
+        }
+        finished
+    }
+
+    /// The entry the running job waits on a choice for
+    pub fn conflict(&self) -> Option<&Conflict> {
+        self.conflict.as_ref()
+    }
+
+    /// Lets the running job go on with the pending conflict
+    pub fn resolve(&mut self, choice: Choice, all: bool) {
+        if self.conflict.take().is_some() {
+            self.answers.send(Answer { choice, all }).ok();
+        }
+    }
+
+    /// Stops the oldest job, returns whether there was one
+    pub fn cancel_current(&mut self) -> bool {
+        match self.jobs.iter().find(|j| !j.is_cancelled()) {
+            Some(job) => {
+                job.cancel.store(true, Ordering::Relaxed);
+                // The worker gives up waiting on it
+                self.conflict = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels everything and waits for the worker to clean up after itself
+    pub fn shutdown(&mut self) {
+        for job in &self.jobs {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+        self.conflict = None;
+        self.tx = None;
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+    }
+}
+
+/// State of the job being carried out on the worker
+struct Ctx<'a> {
+    id: u64,
+    kind: JobKind,
+    cancel: &'a AtomicBool,
+    updates: &'a Sender<Update>,
+    /// Where choices for conflicts come from, without it existing targets are skipped
+    answers: Option<&'a Receiver<Answer>>,
+    /// The choice made for all conflicts of the job
+    policy: Option<Choice>,
+    files: u64,
+    bytes: u64,
+    errors: Vec<String>,
+    skipped: u64,
+    steps: Vec<Step>,
+    replaced: Vec<Step>,
+}
+impl<'a> Ctx<'a> {
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    fn report(&self, current: &Path) {
+        self.updates.send(Update::Progress {
+            id: self.id,
+            files: self.files,
+            bytes: self.bytes,
+            current: current.to_path_buf(),
+        }).ok();
+    }
+
+    fn error(&mut self, path: &Path, e: impl std::fmt::Display) {
+        self.errors.push(format!("{}: {}", path.display(), e));
+    }
+
+    /// Notes that `to` now holds what was in `from`, if it does
+    fn done(&mut self, from: &Path, to: &Path) {
+        if let Some(stamp) = Stamp::of(to) {
+            self.steps.push(Step { from: from.to_path_buf(), to: to.to_path_buf(), stamp });
+        }
+    }
+
+    /// Waits for the user to choose what to do about the conflict, `None` once cancelled
+    fn ask(&self, conflict: Conflict) -> Option<Answer> {
+        let answers = self.answers?;
+        self.updates.send(Update::Conflict(conflict)).ok()?;
+        loop {
+            match answers.recv_timeout(Duration::from_millis(100)) {
+                Ok(answer) => return Some(answer),
+                Err(RecvTimeoutError::Timeout) if !self.cancelled() => {}
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+fn run(job: &Job, updates: &Sender<Update>, answers: &Receiver<Answer>) -> Outcome {
+    let (files, bytes) = job.sources.iter()
+        .map(|s| measure(s))
+        .fold((0, 0), |(f, b), (f2, b2)| (f + f2, b + b2));
+    // Changing attributes reads and writes nothing, its progress goes by entries
+    let bytes = if job.kind == JobKind::Attributes { 0 } else { bytes };
+    updates.send(Update::Started { id: job.id, files, bytes }).ok();
+
+    let mut ctx = Ctx {
+        id: job.id,
+        kind: job.kind,
+        cancel: &job.cancel,
+        updates,
+        answers: Some(answers),
+        policy: None,
+        files: 0,
+        bytes: 0,
+        errors: vec![],
+        skipped: 0,
+        steps: vec![],
+        replaced: vec![],
+    };
+    for (i, source) in job.sources.iter().enumerate() {
+        if ctx.cancelled() { break }
+        match job.kind {
+            JobKind::Copy | JobKind::Move => {
+                let target = match (job.targets.get(i), &job.dest) {
+                    (Some(target), _) => target.clone(),
+                    (None, Some(dest)) => match target_path(&mut ctx, source, dest) {
+                        Some(target) => target,
+                        None => continue,
+                    },
+                    (None, None) => {
+                        ctx.error(source, "no destination");
+                        continue;
+                    }
+                };
+                let whole = match job.kind {
+                    // Noted even when parts of it failed, undoing removes what did get copied.
+                    // A cancelled copy leaves nothing to note, it cleans up after itself.
+                    JobKind::Copy => {
+                        copy_tree(&mut ctx, source, &target);
+                        true
+                    }
+                    // A move which failed halfway is left out, there's no single place to undo it to
+                    _ => move_tree(&mut ctx, source, &target),
+                };
+                if whole {
+                    ctx.done(source, &target);
+                }
+            }
+            JobKind::Trash => {
+                ctx.report(source);
+                let (files, bytes) = measure(source);
+                match trash::trash(source) {
+                    Ok(target) => {
+                        ctx.files += files;
+                        ctx.bytes += bytes;
+                        ctx.done(source, &target);
+                    }
+                    Err(e) => ctx.error(source, e),
+                }
+            }
+            JobKind::Delete => remove_tree(&mut ctx, source, true),
+            JobKind::Attributes => match &job.change {
+                Some(change) => change_tree(&mut ctx, change, source),
+                None => ctx.error(source, "nothing to change"),
+            },
+        }
+    }
+    Outcome {
+        id: job.id,
+        kind: job.kind,
+        files: ctx.files,
+        cancelled: ctx.cancelled(),
+        errors: ctx.errors,
+        skipped: ctx.skipped,
+        steps: ctx.steps,
+        replaced: ctx.replaced,
+        summary: None,
+    }
+}
+
+/// Number of files and bytes below a path, not following symlinks
+fn measure(path: &Path) -> (u64, u64) {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return (0, 0),
+    };
+    if !meta.is_dir() {
+        return (1, meta.len());
+    }
+    let mut total = (1, 0);
+    if let Ok(items) = path.read_dir() {
+        for item in items.flatten() {
+            let (files, bytes) = measure(&item.path());
+            total.0 += files;
+            total.1 += bytes;
+        }
+    }
+    total
+}
+
+/// Where `source` ends up in `dest`, checking it's a sensible place to put it
+fn target_path(ctx: &mut Ctx, source: &Path, dest: &Path) -> Option<PathBuf> {
+    let name = match source.file_name() {
+        Some(name) => name,
+        None => {
+            ctx.error(source, "can't copy or move a root directory");
+            return None;
+        }
+    };
+    let target = dest.join(name);
+    if target == source {
+        ctx.error(source, "source and destination are the same");
+        return None;
+    }
+    if dest.starts_with(source) {
+        ctx.error(source, "can't copy or move a directory into itself");
+        return None;
+    }
+    if fs::symlink_metadata(&target).is_ok() {
+        return resolve(ctx, source, target);
+    }
+    Some(target)
+}
+
+/// Asks what to do about an existing target, unless a choice was made for all of them.
+/// Returns where to put the entry, if anywhere.
+fn resolve(ctx: &mut Ctx, source: &Path, target: PathBuf) -> Option<PathBuf> {
+    let mut conflict = Conflict { kind: ctx.kind, source: Side::of(source), target: Side::of(&target), same: None };
+    loop {
+        let choice = match ctx.policy {
+            Some(choice) => choice,
+            None => {
+                let answer = match ctx.ask(conflict.clone()) {
+                    Some(answer) => answer,
+                    None => {
+                        ctx.skipped += 1;
+                        return None;
+                    }
+                };
+                if answer.all && answer.choice != Choice::Compare {
+                    ctx.policy = Some(answer.choice);
+                }
+                answer.choice
+            }
+        };
+        match choice {
+            Choice::Skip => {}
+            Choice::OverwriteIfNewer if !conflict.source_is_newer() => {}
+            Choice::Rename => return Some(trash::free_name(&target)),
+            Choice::Overwrite | Choice::OverwriteIfNewer => {
+                let stamp = Stamp::of(&target);
+                return match trash::trash(&target) {
+                    Ok(trashed) => {
+                        if let Some(stamp) = stamp {
+                            ctx.replaced.push(Step { from: target.clone(), to: trashed, stamp });
+                        }
+                        Some(target)
+                    }
+                    Err(e) => {
+                        ctx.error(&target, format!("could not move it to the trash to overwrite it: {}", e));
+                        None
+                    }
+                };
+            }
+            Choice::Compare => {
+                conflict.same = Some(same_contents(ctx, source, &target).unwrap_or(false));
+                continue;
+            }
+        }
+        ctx.skipped += 1;
+        return None;
+    }
+}
+
+/// Whether both entries hold the same, recursively for directories
+fn same_contents(ctx: &Ctx, a: &Path, b: &Path) -> io::Result<bool> {
+    let (meta_a, meta_b) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+    if ctx.cancelled() {
+        return Ok(false);
+    }
+    if meta_a.file_type().is_symlink() || meta_b.file_type().is_symlink() {
+        return Ok(meta_a.file_type().is_symlink() && meta_b.file_type().is_symlink() && fs::read_link(a)? == fs::read_link(b)?);
+    }
+    if meta_a.is_dir() || meta_b.is_dir() {
+        if !(meta_a.is_dir() && meta_b.is_dir()) {
+            return Ok(false);
+        }
+        let names = |dir: &Path| -> io::Result<Vec<_>> {
+            let mut names = dir.read_dir()?.map(|e| e.map(|e| e.file_name())).collect::<io::Result<Vec<_>>>()?;
+            names.sort();
+            Ok(names)
+        };
+        let names_a = names(a)?;
+        if names_a != names(b)? {
+            return Ok(false);
+        }
+        for name in names_a {
+            if !same_contents(ctx, &a.join(&name), &b.join(&name))? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+    if meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+    let (mut file_a, mut file_b) = (File::open(a)?, File::open(b)?);
+    let (mut buf_a, mut buf_b) = (vec![0; CHUNK_SIZE], vec![0; CHUNK_SIZE]);
+    loop {
+        if ctx.cancelled() {
+            return Ok(false);
+        }
+        let n = file_a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        file_b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+fn copy_tree(ctx: &mut Ctx, source: &Path, target: &Path) {
+    if ctx.cancelled() { return }
+    let meta = match fs::symlink_metadata(source) {
+        Ok(meta) => meta,
+        Err(e) => return ctx.error(source, e),
+    };
+    ctx.report(source);
+    if meta.file_type().is_symlink() {
+        if let Err(e) = copy_symlink(source, target) {
+            ctx.error(source, e);
+        }
+        ctx.files += 1;
+    } else if meta.is_dir() {
+        if let Err(e) = fs::create_dir(target) {
+            return ctx.error(target, e);
+        }
+        ctx.files += 1;
+        match source.read_dir() {
+            Ok(items) => {
+                for item in items.flatten() {
+                    copy_tree(ctx, &item.path(), &target.join(item.file_name()));
+                }
+            }
+            Err(e) => ctx.error(source, e),
+        }
+        // The directory is this job's own, so nothing of a cancelled copy is left behind
+        if ctx.cancelled() {
+            fs::remove_dir_all(target).ok();
+            return;
+        }
+        // Only now, as copying the contents touched the directory
+        if let Err(e) = copy_attributes(&meta, target) {
+            ctx.error(target, e);
+        }
+    } else {
+        match copy_file(ctx, source, target, &meta) {
+            Ok(()) => ctx.files += 1,
+            Err(e) if ctx.cancelled() && e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => ctx.error(source, e),
+        }
+    }
+}
+
+/// Copies into a temporary sibling of the target and renames it into place when complete,
+/// so a failed or cancelled copy never leaves a half written file behind
+fn copy_file(ctx: &mut Ctx, source: &Path, target: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let partial = target.with_file_name(format!(".{}.pc-part", name));
+    let result = (|| {
+        let mut input = File::open(source)?;
+        let mut output = File::create(&partial)?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            if ctx.cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+            }
+            let n = input.read(&mut buf)?;
+            if n == 0 { break }
+            output.write_all(&buf[..n])?;
+            ctx.bytes += n as u64;
+            ctx.report(source);
+        }
+        drop(output);
+        copy_attributes(meta, &partial)?;
+        fs::rename(&partial, target)
+    })();
+    if result.is_err() {
+        fs::remove_file(&partial).ok();
+    }
+    result
+}
+
+/// Carries over permissions and access and modification times
+fn copy_attributes(meta: &fs::Metadata, target: &Path) -> io::Result<()> {
+    fs::set_permissions(target, meta.permissions())?;
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = meta.modified() {
+        times = times.set_modified(modified);
+    }
+    File::open(target)?.set_times(times)
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, target)
+}
+
+#[cfg(windows)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    let link = fs::read_link(source)?;
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(link, target)
+    } else {
+        std::os::windows::fs::symlink_file(link, target)
+    }
+}
+
+/// Returns whether everything ended up at the target
+fn move_tree(ctx: &mut Ctx, source: &Path, target: &Path) -> bool {
+    ctx.report(source);
+    match fs::rename(source, target) {
+        Ok(()) => {
+            let (files, bytes) = measure(target);
+            ctx.files += files;
+            ctx.bytes += bytes;
+            true
+        }
+        // Renaming only works within a filesystem, otherwise copy and remove the original
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let errors = ctx.errors.len();
+            copy_tree(ctx, source, target);
+            if ctx.cancelled() || ctx.errors.len() != errors {
+                return false;
+            }
+            // The copy counted the files already
+            remove_tree(ctx, source, false);
+            if ctx.cancelled() && ctx.errors.len() == errors {
+                ctx.error(source, "copied, but cancelled before all of it was removed here");
+            }
+            ctx.errors.len() == errors
+        }
+        Err(e) => {
+            ctx.error(source, e);
+            false
+        }
+    }
+}
+
+/// `count` is whether removed files add to the job's progress
+fn change_tree(ctx: &mut Ctx, change: &Change, path: &Path) {
+    if ctx.cancelled() { return }
+    ctx.report(path);
+    let result = change.apply(path, |meta| {
+        if !meta.is_dir() { return }
+        match path.read_dir() {
+            Ok(items) => {
+                for item in items.flatten() {
+                    change_tree(ctx, change, &item.path());
+                }
+            }
+            Err(e) => ctx.error(path, e),
+        }
+    });
+    match result {
+        Ok(()) => ctx.files += 1,
+        Err(e) => ctx.error(path, e),
+    }
+}
+
+fn remove_tree(ctx: &mut Ctx, path: &Path, count: bool) {
+    if ctx.cancelled() { return }
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) => return ctx.error(path, e),
+    };
+    ctx.report(path);
+    if meta.is_dir() {
+        match path.read_dir() {
+            Ok(items) => {
+                for item in items.flatten() {
+                    remove_tree(ctx, &item.path(), count);
+                }
+            }
+            Err(e) => return ctx.error(path, e),
+        }
+        if ctx.cancelled() { return }
+        match fs::remove_dir(path) {
+            Ok(()) if count => ctx.files += 1,
+            Ok(()) => {}
+            Err(e) => ctx.error(path, e),
+        }
+    } else {
+        match fs::remove_file(path) {
+            Ok(()) if count => {
+                ctx.files += 1;
+                ctx.bytes += meta.len();
+            }
+            Ok(()) => {}
+            Err(e) => ctx.error(path, e),
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use crate::{jobs::JobKind, rename, storage, trash};
+
+const JOURNAL_FILE: &str = "journal";
+/// Older operations are forgotten
+const MAX_RECORDS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpKind {
+    Copy,
+    Move,
+    Rename,
+    Trash,
+    Create,
+}
+impl OpKind {
+    fn name(&self) -> &'static str {
+        match self {
+            OpKind::Copy => "copy",
+            OpKind::Move => "move",
+            OpKind::Rename => "rename",
+            OpKind::Trash => "trash",
+            OpKind::Create => "creation",
+        }
+    }
+
+    fn past(&self) -> &'static str {
+        match self {
+            OpKind::Copy => "copied",
+            OpKind::Move => "moved",
+            OpKind::Rename => "renamed",
+            OpKind::Trash => "trashed",
+            OpKind::Create => "created",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "copy" => Some(OpKind::Copy),
+            "move" => Some(OpKind::Move),
+            "rename" => Some(OpKind::Rename),
+            "trash" => Some(OpKind::Trash),
+            "creation" => Some(OpKind::Create),
+            _ => None,
+        }
+    }
+}
+
+/// Fingerprint of an entry and everything below it, to tell whether it was touched since.
+///
+/// Directory times are left out, as moving a directory may update them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp {
+    count: u64,
+    bytes: u64,
+    /// Newest modification time of the files, in nanoseconds since the epoch
+    modified: u128,
+}
+impl Stamp {
+    pub fn of(path: &Path) -> Option<Stamp> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        let mut stamp = Stamp { count: 1, bytes: 0, modified: 0 };
+        if meta.is_dir() {
+            for item in path.read_dir().ok()?.flatten() {
+                let inner = Stamp::of(&item.path())?;
+                stamp.count += inner.count;
+                stamp.bytes += inner.bytes;
+                stamp.modified = stamp.modified.max(inner.modified);
+            }
+        } else {
+            stamp.bytes = meta.len();
+            stamp.modified = meta.modified().ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+        }
+        Some(stamp)
+    }
+
+    fn parse(s: &str) -> Option<Stamp> {
+        let mut parts = s.split(':');
+        Some(Stamp {
+            count: parts.next()?.parse().ok()?,
+            bytes: parts.next()?.parse().ok()?,
+            modified: parts.next()?.parse().ok()?,
+        })
+    }
+
+    fn format(&self) -> String {
+        format!("{}:{}:{}", self.count, self.bytes, self.modified)
+    }
+}
+
+/// One entry of an operation, `to` is the copy, the new place or the place in the trash.
+/// A new entry is put in the trash when undone, `from` is where it went then.
+pub struct Step {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub stamp: Stamp,
+}
+
+pub struct Record {
+    pub kind: OpKind,
+    pub steps: Vec<Step>,
+    /// Entries overwritten by the steps, they went to the trash first and come back when undoing
+    pub replaced: Vec<Step>,
+}
+impl Record {
+    fn describe(&self) -> String {
+        match self.steps.as_slice() {
+            [step] => format!("{} of {}", self.kind.name(), name(&step.from)),
+            steps => format!("{} of {} items", self.kind.name(), steps.len()),
+        }
+    }
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// How an operation gets undone or redone
+pub enum Revert {
+    /// It was, with what to tell about it
+    Done(String),
+    /// Entries to copy or move on the job queue, what it got done goes to `finish_undo` or
+    /// `finish_redo` afterwards
+    Job { kind: JobKind, pairs: Vec<(PathBuf, PathBuf)>, message: String },
+}
+
+/// File operations done from the app, kept in the data directory so they can be undone later.
+///
+/// Stored as one line per operation: state and kind, then `from`, `to` and stamp of every entry,
+/// all separated by tabs, followed by `replaced` and the same for overwritten entries if there
+/// were any. The state is `d` for done and `u` for undone operations. Paths are
+/// percent encoded as in `.trashinfo` files, so tabs, newlines and any bytes in them survive.
+pub struct Journal {
+    file: Option<PathBuf>,
+    records: Vec<Record>,
+    /// Records before this were done, the ones from here on were undone and can be redone
+    pos: usize,
+}
+impl Journal {
+    pub fn load() -> Self {
+        let file = storage::data_dir().map(|d| d.join(JOURNAL_FILE));
+        let mut journal = Journal { file, records: vec![], pos: 0 };
+        let contents = journal.file.as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .unwrap_or_default();
+        for line in contents.lines() {
+            if let Some((done, record)) = parse(line) {
+                // Undone operations can only come after the done ones
+                if done && journal.pos < journal.records.len() { continue }
+                journal.records.push(record);
+                if done {
+                    journal.pos += 1;
+                }
+            }
+        }
+        journal
+    }
+
+    /// Adds an operation which just happened, it can't be redone what was undone before it
+    pub fn record(&mut self, kind: OpKind, steps: Vec<Step>) -> io::Result<()> {
+        self.record_replacing(kind, steps, vec![])
+    }
+
+    /// Adds an operation which overwrote the `replaced` entries, undoing it puts them back too
+    pub fn record_replacing(&mut self, kind: OpKind, steps: Vec<Step>, replaced: Vec<Step>) -> io::Result<()> {
+        // Nothing took the place of the overwritten entries, they were only trashed
+        let (kind, steps, replaced) = if steps.is_empty() { (OpKind::Trash, replaced, vec![]) } else { (kind, steps, replaced) };
+        if steps.is_empty() { return Ok(()) }
+        self.records.truncate(self.pos);
+        self.records.push(Record { kind, steps, replaced });
+        if self.records.len() > MAX_RECORDS {
+            self.records.remove(0);
+        }
+        self.pos = self.records.len();
+        self.save()
+    }
+
+    /// Reverts the last operation, moves are left to a job
+    pub fn undo(&mut self) -> Result<Revert, String> {
+        let record = match self.pos.checked_sub(1).and_then(|i| self.records.get_mut(i)) {
+            Some(record) => record,
+            None => return Err("Nothing to undo".to_string()),
+        };
+        for step in &record.steps {
+            if Stamp::of(&step.to) != Some(step.stamp) {
+                return Err(format!(
+                    "Can't undo the {}, {} was changed or removed since it was {}",
+                    record.describe(), step.to.display(), record.kind.past(),
+                ));
+            }
+            // Renames may swap names around, the original name then belongs to another of the entries
+            let taken_by_other = record.steps.iter().any(|s| s.to == step.from);
+            let kept = matches!(record.kind, OpKind::Copy | OpKind::Create);
+            if !kept && !taken_by_other && fs::symlink_metadata(&step.from).is_ok() {
+                return Err(format!("Can't undo the {}, {} exists again", record.describe(), step.from.display()));
+            }
+        }
+        for replaced in &record.replaced {
+            if Stamp::of(&replaced.to) != Some(replaced.stamp) {
+                return Err(format!(
+                    "Can't undo the {}, the overwritten {} was changed or removed from the trash since",
+                    record.describe(), replaced.from.display(),
+                ));
+            }
+        }
+        if record.kind == OpKind::Rename {
+            let pairs: Vec<(PathBuf, PathBuf)> = record.steps.iter().map(|s| (s.to.clone(), s.from.clone())).collect();
+            rename::rename_all(&pairs).map_err(|e| format!("Undoing the {} failed: {}", record.describe(), e))?;
+        }
+        let describe = record.describe();
+        if record.kind == OpKind::Move {
+            let pairs = record.steps.iter().rev().map(|s| (s.to.clone(), s.from.clone())).collect();
+            return Ok(Revert::Job { kind: JobKind::Move, pairs, message: format!("Undoing the {}", describe) });
+        }
+        // Noted as a job would, so whatever got undone before a failure stays undone
+        let mut done = vec![];
+        let mut failed = None;
+        for step in record.steps.iter().rev() {
+            let result = match record.kind {
+                OpKind::Copy => remove(&step.to).map(|()| step.from.clone()),
+                OpKind::Rename | OpKind::Move => Ok(step.from.clone()),
+                OpKind::Trash => trash::untrash(&step.to, &step.from)
+                    .map(|()| step.from.clone())
+                    .map_err(|e| e.to_string()),
+                // Kept in the trash rather than removed, it may have been written to already
+                OpKind::Create => trash::trash(&step.to).map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(to) => done.push(Step { from: step.to.clone(), to, stamp: step.stamp }),
+                Err(e) => {
+                    failed = Some(format!("{}: {}", step.to.display(), e));
+                    break;
+                }
+            }
+        }
+        let message = self.finish_undo(&done)?;
+        match failed {
+            Some(e) => Err(format!("{}, failed: {}", message, e)),
+            None => Ok(Revert::Done(message)),
+        }
+    }
+
+    /// Notes what undoing the last operation got done, returning what that was.
+    /// The entries which weren't reverted stay a done operation of their own.
+    pub fn finish_undo(&mut self, done: &[Step]) -> Result<String, String> {
+        let record = match self.pos.checked_sub(1).and_then(|i| self.records.get_mut(i)) {
+            Some(record) => record,
+            None => return Err("Nothing to undo".to_string()),
+        };
+        let describe = record.describe();
+        let undone = take_done(record, done, true);
+        if undone.steps.is_empty() {
+            return Ok(format!("Nothing of the {} was undone", describe));
+        }
+        let restored = restore(&undone.replaced);
+        let message = if record.steps.is_empty() {
+            *record = undone;
+            self.pos -= 1;
+            format!("Undid the {}", describe)
+        } else {
+            self.records.insert(self.pos, undone);
+            format!("Undid the {} in part", describe)
+        };
+        self.save().map_err(|e| format!("Could not save the journal: {}", e))?;
+        restored.map_err(|e| format!("{}, but could not put back what it overwrote: {}", message, e))?;
+        Ok(message)
+    }
+
+    /// Does the last undone operation again, copies and moves are left to a job
+    pub fn redo(&mut self) -> Result<Revert, String> {
+        let record = match self.records.get_mut(self.pos) {
+            Some(record) => record,
+            None => return Err("Nothing to redo".to_string()),
+        };
+        for step in &record.steps {
+            if Stamp::of(&step.from) != Some(step.stamp) {
+                return Err(format!(
+                    "Can't redo the {}, {} was changed or removed since",
+                    record.describe(), step.from.display(),
+                ));
+            }
+            let taken_by_other = record.steps.iter().any(|s| s.from == step.to);
+            let overwritten = record.replaced.iter().any(|r| r.from == step.to);
+            if record.kind != OpKind::Trash && !taken_by_other && !overwritten && fs::symlink_metadata(&step.to).is_ok() {
+                return Err(format!("Can't redo the {}, {} exists", record.describe(), step.to.display()));
+            }
+        }
+        let describe = record.describe();
+        if let OpKind::Copy | OpKind::Move = record.kind {
+            let kind = if record.kind == OpKind::Copy { JobKind::Copy } else { JobKind::Move };
+            let pairs = record.steps.iter().map(|s| (s.from.clone(), s.to.clone())).collect();
+            // Out of the way again, unless putting them back failed when undoing
+            let mut trashed = Ok(());
+            for replaced in &mut record.replaced {
+                if let Some(stamp) = Stamp::of(&replaced.from) {
+                    match trash::trash(&replaced.from) {
+                        Ok(to) => {
+                            replaced.to = to;
+                            replaced.stamp = stamp;
+                        }
+                        Err(e) => {
+                            trashed = Err(format!("Redoing the {} failed: {}: {}", describe, replaced.from.display(), e));
+                            break;
+                        }
+                    }
+                }
+            }
+            if let Err(e) = trashed {
+                // Where the ones which did go to the trash are now
+                self.save().ok();
+                return Err(e);
+            }
+            return Ok(Revert::Job { kind, pairs, message: format!("Redoing the {}", describe) });
+        }
+        if record.kind == OpKind::Rename {
+            let pairs: Vec<(PathBuf, PathBuf)> = record.steps.iter().map(|s| (s.from.clone(), s.to.clone())).collect();
+            rename::rename_all(&pairs).map_err(|e| format!("Redoing the {} failed: {}", describe, e))?;
+        }
+        let mut done = vec![];
+        let mut failed = None;
+        for step in &record.steps {
+            let result = match record.kind {
+                OpKind::Copy | OpKind::Move | OpKind::Rename => Ok(step.to.clone()),
+                // The trash may well put it somewhere else this time
+                OpKind::Trash => trash::trash(&step.from).map_err(|e| e.to_string()),
+                OpKind::Create => trash::untrash(&step.from, &step.to)
+                    .map(|()| step.to.clone())
+                    .map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(to) => done.push(Step { from: step.from.clone(), to, stamp: step.stamp }),
+                Err(e) => {
+                    failed = Some(format!("{}: {}", step.from.display(), e));
+                    break;
+                }
+            }
+        }
+        let message = self.finish_redo(&done)?;
+        match failed {
+            Some(e) => Err(format!("{}, failed: {}", message, e)),
+            None => Ok(Revert::Done(message)),
+        }
+    }
+
+    /// Notes what redoing the last undone operation got done, returning what that was.
+    /// The entries it didn't get to stay an undone operation of their own.
+    pub fn finish_redo(&mut self, done: &[Step]) -> Result<String, String> {
+        let record = match self.records.get_mut(self.pos) {
+            Some(record) => record,
+            None => return Err("Nothing to redo".to_string()),
+        };
+        let describe = record.describe();
+        let redone = take_done(record, done, false);
+        // What the entries which weren't redone would have overwritten comes back
+        let restored = restore(&record.replaced);
+        if redone.steps.is_empty() {
+            self.save().map_err(|e| format!("Could not save the journal: {}", e))?;
+            restored.map_err(|e| format!("Nothing of the {} was redone, and could not put back what it overwrote: {}", describe, e))?;
+            return Ok(format!("Nothing of the {} was redone", describe));
+        }
+        let message = if record.steps.is_empty() {
+            *record = redone;
+            format!("Redid the {}", describe)
+        } else {
+            self.records.insert(self.pos, redone);
+            format!("Redid the {} in part", describe)
+        };
+        self.pos += 1;
+        self.save().map_err(|e| format!("Could not save the journal: {}", e))?;
+        restored.map_err(|e| format!("{}, but could not put back what the rest overwrote: {}", message, e))?;
+        Ok(message)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no data directory to save the journal to")),
+        };
+        let mut s = String::new();
+        for (i, record) in self.records.iter().enumerate() {
+            s.push_str(&format(i < self.pos, record));
+            s.push('\n');
+        }
+        storage::write_atomic(file, &s)
+    }
+}
+
+/// One line of the journal file, without the line break
+fn format(done: bool, record: &Record) -> String {
+    let mut s = format!("{}\t{}", if done { "d" } else { "u" }, record.kind.name());
+    for step in &record.steps {
+        s.push_str(&format_step(step));
+    }
+    if !record.replaced.is_empty() {
+        s.push_str("\treplaced");
+        for step in &record.replaced {
+            s.push_str(&format_step(step));
+        }
+    }
+    s
+}
+
+fn parse(line: &str) -> Option<(bool, Record)> {
+    let mut fields = line.split('\t');
+    let done = match fields.next()? {
+        "d" => true,
+        "u" => false,
+        _ => return None,
+    };
+    let kind = OpKind::parse(fields.next()?)?;
+    let fields: Vec<&str> = fields.collect();
+    let (steps, replaced) = match fields.iter().position(|f| *f == "replaced") {
+        Some(i) => (parse_steps(&fields[..i])?, parse_steps(&fields[i + 1..])?),
+        None => (parse_steps(&fields)?, vec![]),
+    };
+    if steps.is_empty() { return None }
+    Some((done, Record { kind, steps, replaced }))
+}
+
+fn parse_steps(fields: &[&str]) -> Option<Vec<Step>> {
+    if !fields.len().is_multiple_of(3) { return None }
+    fields.chunks(3)
+        .map(|step| Some(Step {
+            from: trash::decode(step[0]),
+            to: trash::decode(step[1]),
+            stamp: Stamp::parse(step[2])?,
+        }))
+        .collect()
+}
+
+fn format_step(step: &Step) -> String {
+    format!("\t{}\t{}\t{}", trash::encode(&step.from), trash::encode(&step.to), step.stamp.format())
+}
+
+/// Takes the steps which were undone or redone out of the record, with the entries they overwrote.
+/// `done` holds where each entry was taken from and where it went, as stamped now. The overwritten
+/// entries of the steps left stay with them.
+fn take_done(record: &mut Record, done: &[Step], undo: bool) -> Record {
+    let (mut taken, mut kept) = (vec![], vec![]);
+    for mut step in record.steps.drain(..) {
+        let at = if undo { &step.to } else { &step.from };
+        match done.iter().find(|d| d.from == *at) {
+            Some(d) => {
+                // The trash may have picked a new place for it
+                if undo {
+                    step.from = d.to.clone();
+                } else {
+                    step.to = d.to.clone();
+                }
+                step.stamp = d.stamp;
+                taken.push(step);
+            }
+            None => kept.push(step),
+        }
+    }
+    let (mut replaced, mut kept_replaced) = (vec![], vec![]);
+    for entry in record.replaced.drain(..) {
+        if taken.is_empty() || kept.iter().any(|s| s.to == entry.from) {
+            kept_replaced.push(entry);
+        } else {
+            replaced.push(entry);
+        }
+    }
+    record.steps = kept;
+    record.replaced = kept_replaced;
+    Record { kind: record.kind, steps: taken, replaced }
+}
+
+/// Puts overwritten entries back from the trash
+fn restore(replaced: &[Step]) -> Result<(), String> {
+    for entry in replaced {
+        trash::untrash(&entry.to, &entry.from).map_err(|e| format!("{}: {}", entry.from.display(), e))?;
+    }
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    let result = if fs::symlink_metadata(path).map(|m| m.is_dir()).unwrap_or(false) {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_round_trips() {
+        let stamp = Stamp { count: 3, bytes: 4096, modified: 1_700_000_000_123_456_789 };
+        let step = |from: &str, to: &str| Step { from: PathBuf::from(from), to: PathBuf::from(to), stamp };
+        let record = Record {
+            kind: OpKind::Move,
+            steps: vec![
+                step("/tmp/über/naïve 100%.txt", "/tmp/dest/naïve 100%.txt"),
+                step("/tmp/with\ttab", "/tmp/dest/new\nline %41"),
+            ],
+            replaced: vec![step("/tmp/dest/with\ttab", "/tmp/Trash/files/with\ttab")],
+        };
+        let line = format(true, &record);
+        assert!(!line.contains('\n'));
+        let (done, parsed) = parse(&line).unwrap();
+        assert!(done);
+        assert_eq!(parsed.kind, OpKind::Move);
+        let fields = |steps: &[Step]| steps.iter().map(|s| (s.from.clone(), s.to.clone(), s.stamp)).collect::<Vec<_>>();
+        assert_eq!(fields(&parsed.steps), fields(&record.steps));
+        assert_eq!(fields(&parsed.replaced), fields(&record.replaced));
+    }
+
+    #[test]
+    fn rejects_broken_lines() {
+        assert!(parse("d\tmove").is_none());
+        assert!(parse("x\tmove\t/a\t/b\t1:1:1").is_none());
+        assert!(parse("d\tmove\t/a\t/b\tnot a stamp").is_none());
+        assert!(parse("d\tmove\t/a\t/b").is_none());
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use tui::widgets::ListState;
+use crate::{input::LineInput, storage};
+
+const DB_FILE: &str = "dirs";
+/// Once the counts add up to this, all of them are scaled down so old entries fade out
+const MAX_TOTAL_COUNT: f64 = 10_000.0;
+/// How many matches the jump prompt lists
+const PROMPT_MATCHES: usize = 10;
+
+struct Visit {
+    path: PathBuf,
+    count: f64,
+    last: u64,
+}
+impl Visit {
+    /// Visit count weighted by how recent the last visit was
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last);
+        let weight = if age < 60 * 60 {
+            4.0
+        } else if age < 24 * 60 * 60 {
+            2.0
+        } else if age < 7 * 24 * 60 * 60 {
+            0.5
+        } else {
+            0.25
+        };
+        self.count * weight
+    }
+}
+
+/// Visited directories with visit counts and times, ranked by frecency
+pub struct JumpDb {
+    file: Option<PathBuf>,
+    visits: Vec<Visit>,
+    /// Visits made since the last save, counted and with the latest time,
+    /// merged into whatever other sessions saved meanwhile
+    added: HashMap<PathBuf, (f64, u64)>,
+    /// Directories found to be gone since the last save
+    removed: Vec<PathBuf>,
+}
+impl JumpDb {
+    /// Loads the database from the data directory, starting empty if there is none
+    pub fn load() -> Self {
+        let file = storage::data_dir().map(|d| d.join(DB_FILE));
+        let visits = file.as_deref().map(read).unwrap_or_default();
+        JumpDb { file, visits, added: HashMap::new(), removed: vec![] }
+    }
+
+    pub fn visit(&mut self, path: &Path) {
+        let now = storage::now();
+        add(&mut self.visits, path, 1.0, now);
+        let added = self.added.entry(path.to_path_buf()).or_insert((0.0, now));
+        added.0 += 1.0;
+        added.1 = now;
+        age(&mut self.visits);
+    }
+
+    /// Directories matching all the words, best first.
+    /// Entries pointing to directories which no longer exist are dropped on the way.
+    pub fn query(&mut self, words: &[&str]) -> Vec<PathBuf> {
+        let (visits, gone): (Vec<Visit>, Vec<Visit>) = std::mem::take(&mut self.visits)
+            .into_iter()
+            .partition(|v| v.path.is_dir());
+        self.visits = visits;
+        self.removed.extend(gone.into_iter().map(|v| v.path));
+
+        let now = storage::now();
+        let mut matches: Vec<&Visit> = self.visits.iter()
+            .filter(|v| matches_words(&v.path, words))
+            .collect();
+        matches.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        matches.into_iter().map(|v| v.path.clone()).collect()
+    }
+
+    /// Merges the visits of this session into the database as it is on disk now,
+    /// so sessions running side by side don't drop each other's visits
+    pub fn save(&mut self) -> io::Result<()> {
+        let file = match &self.file {
+            Some(file) if !self.added.is_empty() || !self.removed.is_empty() => file,
+            _ => return Ok(()),
+        };
+        let mut visits = read(file);
+        visits.retain(|v| !self.removed.contains(&v.path));
+        for (path, &(count, last)) in &self.added {
+            add(&mut visits, path, count, last);
+        }
+        age(&mut visits);
+        let mut s = String::new();
+        for visit in &visits {
+            let path = visit.path.to_string_lossy();
+            if path.contains('\n') { continue }
+            s.push_str(&format!("{}\t{}\t{}\n", visit.count, visit.last, path));
+        }
+        storage::write_atomic(file, &s)?;
+        self.visits = visits;
+        self.added.clear();
+        self.removed.clear();
+        Ok(())
+    }
+}
+
+fn read(file: &Path) -> Vec<Visit> {
+    fs::read_to_string(file).map(|s| parse(&s)).unwrap_or_default()
+}
+
+/// Counts `count` more visits to `path`, the last one at `last`
+fn add(visits: &mut Vec<Visit>, path: &Path, count: f64, last: u64) {
+    match visits.iter_mut().find(|v| v.path == path) {
+        Some(visit) => {
+            visit.count += count;
+            visit.last = visit.last.max(last);
+        }
+        None => visits.push(Visit { path: path.to_path_buf(), count, last }),
+    }
+}
+
+fn age(visits: &mut Vec<Visit>) {
+    let total: f64 = visits.iter().map(|v| v.count).sum();
+    if total > MAX_TOTAL_COUNT {
+        for visit in visits.iter_mut() {
+            visit.count *= 0.9;
+        }
+        visits.retain(|v| v.count >= 1.0);
+    }
+}
+
+fn parse(s: &str) -> Vec<Visit> {
+    s.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let count = fields.next()?.parse().ok()?;
+            let last = fields.next()?.parse().ok()?;
+            let path = PathBuf::from(fields.next()?);
+            Some(Visit { path, count, last })
+        })
+        .collect()
+}
+
+/// All the words have to appear in the path in order, ignoring case,
+/// and the last one has to be in the final component, like `foo ba` matching `/foo/bar`
+fn matches_words(path: &Path, words: &[&str]) -> bool {
+    let path_str = path.to_string_lossy().to_lowercase();
+    let mut from = 0;
+    for word in words {
+        let word = word.to_lowercase();
+        match path_str[from..].find(&word) {
+            Some(i) => from += i + word.len(),
+            None => return false,
+        }
+    }
+    match (words.last(), path.file_name()) {
+        (Some(last), Some(name)) => name.to_string_lossy().to_lowercase().contains(&last.to_lowercase()),
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+/// The `z` prompt, listing the best matches for the typed words
+pub struct JumpPrompt {
+    pub input: LineInput,
+    matches: Vec<PathBuf>,
+    pub state: ListState,
+}
+impl JumpPrompt {
+    pub fn new(db: &mut JumpDb) -> Self {
+        let mut prompt = JumpPrompt {
+            input: LineInput::new(),
+            matches: vec![],
+            state: ListState::default(),
+        };
+        prompt.update(db);
+        prompt
+    }
+
+    /// Re-runs the query after the input changed
+    pub fn update(&mut self, db: &mut JumpDb) {
+        let words: Vec<&str> = self.input.text().split_whitespace().collect();
+        self.matches = db.query(&words);
+        self.matches.truncate(PROMPT_MATCHES);
+        self.state.select(if self.matches.is_empty() { None } else { Some(0) });
+    }
+
+    pub fn matches(&self) -> &[PathBuf] {
+        &self.matches
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.state.selected().and_then(|i| self.matches.get(i)).map(|p| p.as_path())
+    }
+
+    pub fn next(&mut self) {
+        if self.matches.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| (i + 1) % self.matches.len());
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self) {
+        if self.matches.is_empty() { return }
+        let i = self.state.selected().map_or(0, |i| if i == 0 { self.matches.len() - 1 } else { i - 1 });
+        self.state.select(Some(i));
+    }
+}
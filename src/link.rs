@@ -0,0 +1,113 @@
+use std::{
+    fs,
+    io,
+    path::{Component, Path, PathBuf},
+};
+use crate::{goto, input::LineInput};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    Absolute,
+    Relative,
+    Hard,
+}
+impl LinkKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LinkKind::Absolute => "absolute symlink",
+            LinkKind::Relative => "relative symlink",
+            LinkKind::Hard => "hardlink",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            LinkKind::Absolute => LinkKind::Relative,
+            LinkKind::Relative => LinkKind::Hard,
+            LinkKind::Hard => LinkKind::Absolute,
+        }
+    }
+}
+
+/// `to` as seen from the directory `dir`, both absolute
+pub fn relative_path(dir: &Path, to: &Path) -> PathBuf {
+    let dir: Vec<Component> = dir.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut path: PathBuf = dir[common..].iter().map(|_| Component::ParentDir).collect();
+    path.extend(&to[common..]);
+    if path.as_os_str().is_empty() {
+        path.push(Component::CurDir);
+    }
+    path
+}
+
+/// Where a symlink points, resolved against the directory it is in
+pub fn link_target(link: &Path) -> io::Result<PathBuf> {
+    let target = fs::read_link(link)?;
+    let dir = link.parent().unwrap_or(link);
+    Ok(goto::normalize(&dir.join(target)))
+}
+
+/// Makes a link to `source` with the same name in `dir`, returning the link
+pub fn create_link(kind: LinkKind, source: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let name = source.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "can't link a root directory"))?;
+    let link = dir.join(name);
+    if link == source {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the link would replace the entry itself"));
+    }
+    match kind {
+        LinkKind::Absolute => symlink(source, source, &link)?,
+        LinkKind::Relative => symlink(&relative_path(dir, source), source, &link)?,
+        LinkKind::Hard if source.is_dir() => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "directories can't be hardlinked"));
+        }
+        LinkKind::Hard => fs::hard_link(source, &link)?,
+    }
+    Ok(link)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, _source: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, source: &Path, link: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+/// Typing the directory to put links to the selected entries in
+pub struct LinkPrompt {
+    pub sources: Vec<PathBuf>,
+    pub kind: LinkKind,
+    pub input: LineInput,
+    pub error: Option<String>,
+}
+impl LinkPrompt {
+    /// Starts out with `dir`, usually the previously visited directory
+    pub fn new(sources: Vec<PathBuf>, dir: Option<&Path>) -> Self {
+        let mut input = LineInput::new();
+        if let Some(dir) = dir {
+            input.set_text(&dir.display().to_string());
+        }
+        LinkPrompt { sources, kind: LinkKind::Absolute, input, error: None }
+    }
+
+    pub fn cycle_kind(&mut self) {
+        self.kind = self.kind.next();
+    }
+
+    /// The directory typed in, relative ones are taken from `cwd`
+    pub fn dir(&self, cwd: &Path) -> Result<PathBuf, String> {
+        let dir = goto::resolve(self.input.text(), cwd)?;
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
+        }
+        Ok(dir)
+    }
+}
@@ -0,0 +1,49 @@
+/// Signatures at the start of a file, or at an offset into it, with the kind of file they mark
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"\xff\xd8\xff", "JPEG image"),
+    (0, b"GIF87a", "GIF image"),
+    (0, b"GIF89a", "GIF image"),
+    (8, b"WEBP", "WebP image"),
+    (0, b"BM", "BMP image"),
+    (0, b"II*\x00", "TIFF image"),
+    (0, b"MM\x00*", "TIFF image"),
+    (0, b"\x00\x00\x01\x00", "Windows icon"),
+    (0, b"8BPS", "Photoshop image"),
+    (0, b"%PDF-", "PDF document"),
+    (0, b"PK\x03\x04", "Zip archive"),
+    (0, b"PK\x05\x06", "Zip archive"),
+    (0, b"\x1f\x8b", "gzip archive"),
+    (0, b"BZh", "bzip2 archive"),
+    (0, b"\xfd7zXZ\x00", "xz archive"),
+    (0, b"\x28\xb5\x2f\xfd", "Zstandard archive"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (0, b"Rar!\x1a\x07", "RAR archive"),
+    (257, b"ustar", "tar archive"),
+    (0, b"!<arch>\n", "ar archive"),
+    (0, b"\x7fELF", "ELF executable"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+    (0, b"\xce\xfa\xed\xfe", "Mach-O executable"),
+    (0, b"\xca\xfe\xba\xbe", "Java class or Mach-O universal binary"),
+    (0, b"MZ", "Windows executable"),
+    (0, b"\x00asm", "WebAssembly module"),
+    (0, b"SQLite format 3\x00", "SQLite database"),
+    (0, b"ID3", "MP3 audio"),
+    (0, b"fLaC", "FLAC audio"),
+    (0, b"OggS", "Ogg media"),
+    (8, b"WAVE", "WAV audio"),
+    (8, b"AVI ", "AVI video"),
+    (4, b"ftyp", "MP4 media"),
+    (0, b"\x1a\x45\xdf\xa3", "Matroska media"),
+    (0, b"wOFF", "WOFF font"),
+    (0, b"wOF2", "WOFF2 font"),
+    (0, b"OTTO", "OpenType font"),
+    (0, b"\x00\x01\x00\x00\x00", "TrueType font"),
+];
+
+/// The kind of file starting with `head`, from the signatures of well known formats
+pub fn file_type(head: &[u8]) -> Option<&'static str> {
+    SIGNATURES.iter()
+        .find(|(offset, signature, _)| head.get(*offset..offset + signature.len()) == Some(*signature))
+        .map(|(_, _, name)| *name)
+}
@@ -1,5 +1,12 @@
 use std::{
-    sync::mpsc,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+        Arc,
+    },
     time::{Duration, Instant},
     thread,
     error::Error,
@@ -14,7 +21,7 @@ use crossterm::{
     terminal::{
         EnterAlternateScreen, LeaveAlternateScreen, enable_raw_mode, disable_raw_mode
     },
-    event::{self, Event as CEvent, KeyCode},
+    event::{self, Event as CEvent, KeyCode, KeyModifiers},
 };
 
 #[cfg(windows)]
@@ -27,6 +34,31 @@ use crossterm::event::DisableMouseCapture;
 use std::io::{stderr, Stderr};
 
 mod app;
+mod bookmarks;
+mod create;
+mod cursor;
+mod entry;
+mod filter;
+mod finder;
+mod goto;
+mod hexdump;
+mod highlight;
+mod history;
+mod input;
+mod jobs;
+mod journal;
+mod jump;
+mod link;
+mod magic;
+mod mode;
+mod pager;
+mod preview;
+mod props;
+mod rename;
+mod selection;
+mod sort;
+mod storage;
+mod trash;
 mod ui;
 mod cwd;
 
@@ -36,19 +68,98 @@ enum Event<I> {
     Tick, // Needed to keep alive window resizing
 }
 
+/// Tells the input handling thread to leave the terminal alone, while another program uses it
+#[derive(Clone, Default)]
+struct InputPause {
+    paused: Arc<AtomicBool>,
+    /// Set by the input thread once it stopped reading
+    idle: Arc<AtomicBool>,
+}
+impl InputPause {
+    /// Returns once the input thread is no longer reading events
+    fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        while !self.idle.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn resume(&self) {
+        self.idle.store(false, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+    }
+}
+
 /**
 Partial Commander
     A simple console based directory tree navigator
 
 Navigation keys:
-    Q|ESC                   Quit the application
+    Q|ESC                   Quit the application (ESC clears the filter and
+                            the selection first)
     Backspace|Left arrow    Move up a directory
     Enter|Right arrow       Move into selected directory
     Up|Down                 Movce within a directory
+    /                       Filter the current directory, Tab switches between
+                            substring, glob and fuzzy matching, Esc clears it
+    f                       Find files recursively below the current directory
+    Alt-Left|Alt-Right      Go back and forward in the visited directories
+    -                       Go to the previously visited directory
+    H                       List recently visited directories
+    :|g                     Go to a typed path, Tab completes
+    z                       Jump to a frequently visited directory
+    m<key>                  Bookmark the current directory under the key
+    '<key>                  Go to the bookmarked directory
+    b                       List, edit and delete bookmarks
+    .                       Show or hide dotfiles
+    Space                   Select or deselect an entry, selections are kept
+                            while moving between directories
+    Ctrl-a|*|+              Select all entries, invert the selection, select
+                            by a glob pattern
+    y|x                     Yank the selected entries for copying, or cut them
+                            for moving
+    p                       Paste yanked or cut entries into the current directory,
+                            asking what to do about existing ones: overwrite,
+                            skip, rename, overwrite if newer or compare
+    d|Delete                Move the selected entries to the trash
+    D|Shift-Delete          Delete the selected entries permanently
+    n|N                     Create a file or a directory, along with the
+                            directories on its path, Tab picks a template
+                            for the file from the templates config directory
+    L                       Link the selected entries into another directory,
+                            the previously visited one by default, Tab
+                            switches between absolute and relative symlinks
+                            and hardlinks
+    J                       Jump to the target of the selected symlink
+    Shift-Up|Shift-Down     Scroll the preview of the selected file, by a
+    PgUp|PgDn               line or by a page, binary files are previewed
+                            as a hex dump
+    v                       View the selected file over the whole screen,
+                            / searches, n|N go to the next or previous match,
+                            : goes to a line, or an offset in binary files,
+                            w toggles wrapping and # line numbers,
+                            Left|Right scroll long lines
+    i                       Show the details of the selected entry, edit its
+                            permissions, owner and group, also for everything
+                            inside a directory
+    r|F2                    Rename the selected entry
+    E                       Rename the selected entries, or all in the current
+                            directory, in $EDITOR
+    R                       Rename the selected entries by a regex and a
+                            template with {{n}}, {{name}}, {{ext}} and {{mtime}}
+    u|Ctrl-r                Undo or redo the last file operation, even
+                            from an earlier session
+    T                       Browse the trash, restore (Enter) or purge (d)
+                            trashed entries
+    Ctrl-c                  Cancel the running copy, move or delete
+    o<key>                  Change sort order: (n)ame, (N)atural, (e)xtension,
+                            (s)ize, (m)time, (c)time, (r)everse,
+                            toggle (d)irectories first, case (i)nsensitivity
 */
 #[derive(Debug, FromArgs)]
 struct Cli {
-    /// path to start in
+    /// path to start in, a file to start with it selected,
+    /// @name of a bookmark or - for the previous directory
     #[argh(positional)]
     path: Option<String>,
     /// time in ms between ticks for input handling
@@ -60,10 +171,41 @@ struct Cli {
     /// display directories only
     #[argh(switch, short = 'd')]
     dirs: bool,
+    /// sort entries by: name, natural, ext, size, mtime or ctime
+    #[argh(option, default = "sort::SortKey::Name", short = 's')]
+    sort: sort::SortKey,
+    /// reverse the sort order
+    #[argh(switch, short = 'r')]
+    reverse: bool,
+    /// hide dotfiles
+    #[argh(switch, short = 'H')]
+    no_hidden: bool,
+    /// print the best match from the jump database for the given words and exit
+    #[argh(option)]
+    query: Option<String>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let cli: Cli = argh::from_env();
+    let cli = parse_cli();
+
+    if let Some(query) = cli.query {
+        return print_jump(&query);
+    }
+
+    let sort = sort::SortOrder {
+        key: cli.sort,
+        reverse: cli.reverse,
+        ..Default::default()
+    };
+    let mut app = app::App::new(cli.path, cli.dirs, !cli.no_hidden, sort)?;
+    let mut current_directory = ui::Folder::new(app.list_cwd_children()?);
+    let start_selection = app.take_start_selection();
+    if !start_selection.is_some_and(|p| current_directory.select_path(&p)) {
+        current_directory.select(Some(app.remembered_idx(current_directory.items()).unwrap_or(0)));
+    }
+
+    let mut parent_directory = ui::Folder::new(app.parent_children().unwrap_or_default());
+    parent_directory.select(app.cwd_parent_idx(parent_directory.items()));
 
     // Setup terminal gui stuff
     enable_raw_mode()?;
@@ -77,15 +219,24 @@ fn main() -> Result<(), Box<dyn Error>> {
     let (tx, rx) = mpsc::channel();
     let (tx_stop_thread, rx_stop_thread) = mpsc::channel();
     let tick_rate = Duration::from_millis(cli.tick_rate);
+    let preview_tx = tx.clone();
+    let input_pause = InputPause::default();
+    let thread_pause = input_pause.clone();
     let input_thread_handle = thread::spawn(move || {
         let mut last_tick = Instant::now();
-        while let Err(_) = rx_stop_thread.recv_timeout(Duration::from_millis(1)) {
+        while rx_stop_thread.recv_timeout(Duration::from_millis(1)).is_err() {
+            if thread_pause.paused.load(Ordering::SeqCst) {
+                thread_pause.idle.store(true, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            thread_pause.idle.store(false, Ordering::SeqCst);
             let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
             if event::poll(timeout).unwrap() {
                 if let CEvent::Key(key) = event::read().unwrap() {
-                    if let Err(_) = tx.send(Event::Input(key)) {
+                    if tx.send(Event::Input(key)).is_err() {
                         return;
                     }
                 }
@@ -100,65 +251,1020 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     terminal.clear()?;
 
-    let mut app = app::App::new(cli.path, cli.dirs)?;
-    let mut current_directory = ui::Folder::new(app.list_cwd_child_names()?);
-    current_directory.set_items(app.list_cwd_child_names()?);
-    current_directory.select(Some(0));
-
-    let mut parent_directory = ui::Folder::new(app.parent_children_names()?);
-    parent_directory.set_items(app.parent_children_names()?);
-    parent_directory.select(app.cwd_parent_idx().ok());
+    let previewer = preview::Previewer::spawn(move || { preview_tx.send(Event::Tick).ok(); });
+    let mut ui_data = crate::ui::UiData::new(previewer);
 
-    let mut ui_data = crate::ui::UiData::new();
+    let mut mode = mode::Mode::Normal;
 
-    terminal.draw(|f| ui::draw(f, &mut app, false, &mut ui_data, &mut current_directory.state, &mut parent_directory.state))?;
+    terminal.draw(|f| ui::draw(f, &mut app, &mut mode, false, &mut ui_data, &mut current_directory, &mut parent_directory))?;
     let mut redraw_only = true;
+    // First key of a two key command, like `o` for sorting
+    let mut pending: Option<char> = None;
     loop {
-        terminal.draw(|f| ui::draw(f, &mut app, redraw_only, &mut ui_data, &mut current_directory.state, &mut parent_directory.state))?;
+        terminal.draw(|f| ui::draw(f, &mut app, &mut mode, redraw_only, &mut ui_data, &mut current_directory, &mut parent_directory))?;
         redraw_only = true;
-        match rx.recv()? {
-            Event::Input(event) => match event.code {
-                KeyCode::Char('q') | KeyCode::Esc => { break }
-                KeyCode::Down => { current_directory.next() }
-                KeyCode::Up => { current_directory.previous() }
-                KeyCode::Left | KeyCode::Backspace => { 
-                    if app.up(current_directory.state.selected()).is_ok() {
-                        current_directory.set_items(app.list_cwd_child_names()?);
-                        current_directory.select(parent_directory.state.selected());
-                        parent_directory.set_items(app.parent_children_names().unwrap_or(Vec::new()));
-                        parent_directory.select(app.cwd_parent_idx().ok());
+        let event = match rx.recv()? {
+            Event::Input(event) => event,
+            Event::Tick => {
+                match &mut mode {
+                    mode::Mode::Finder(finder) => finder.poll(),
+                    mode::Mode::Pager(pager) => pager.poll(),
+                    _ => {}
+                }
+                let finished = app.poll_jobs();
+                match mode {
+                    mode::Mode::Normal if app.jobs().conflict().is_some() => {
+                        mode = mode::Mode::Conflict(mode::ConflictDialog::default());
+                        redraw_only = false;
+                    }
+                    mode::Mode::Conflict(_) if !app.jobs().is_busy() => { mode = mode::Mode::Normal }
+                    _ => {}
+                }
+                if !finished.is_empty() {
+                    for outcome in &finished {
+                        report_job(&mut ui_data, outcome);
+                    }
+                    reload(&app, &mut current_directory, &mut parent_directory)?;
+                    redraw_only = false;
+                }
+                continue;
+            }
+        };
+        match &mut mode {
+            mode::Mode::Normal => {}
+            mode::Mode::Filter => {
+                match event.code {
+                    KeyCode::Esc => {
+                        current_directory.set_filter(None);
+                        mode = mode::Mode::Normal;
+                    }
+                    KeyCode::Enter => {
+                        mode = mode::Mode::Normal;
+                        if current_directory.filter().is_some_and(|f| f.query().is_empty()) {
+                            current_directory.set_filter(None);
+                        }
+                        redraw_only = !go_down(&mut app, &mut current_directory, &mut parent_directory)?;
+                    }
+                    KeyCode::Down => { current_directory.next() }
+                    KeyCode::Up => { current_directory.previous() }
+                    KeyCode::Tab => {
+                        if let Some(filter) = current_directory.filter_mut() {
+                            filter.mode = filter.mode.next();
+                        }
+                        current_directory.refilter();
+                    }
+                    _ => {
+                        if current_directory.filter_mut().is_some_and(|f| f.input.handle_key(event)) {
+                            current_directory.refilter();
+                        }
+                    }
+                }
+                continue;
+            }
+            mode::Mode::Jump(prompt) => {
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Down | KeyCode::Tab => { prompt.next() }
+                    KeyCode::Up | KeyCode::BackTab => { prompt.previous() }
+                    KeyCode::Enter => {
+                        if let Some(target) = prompt.selected().map(|p| p.to_path_buf()) {
+                            mode = mode::Mode::Normal;
+                            if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, &target, None) {
+                                ui_data.set_error(format!("Can't open {}: {}", target.display(), e));
+                            }
+                            redraw_only = false;
+                        }
+                    }
+                    _ => {
+                        if prompt.input.handle_key(event) {
+                            prompt.update(app.jumps_mut());
+                        }
+                    }
+                }
+                continue;
+            }
+            mode::Mode::Bookmarks(popup) if popup.edit.is_some() => {
+                match event.code {
+                    KeyCode::Esc => {
+                        popup.edit = None;
+                        popup.error = None;
+                    }
+                    KeyCode::Enter => popup.commit_edit(app.bookmarks_mut()),
+                    _ => {
+                        if let Some((_, input)) = &mut popup.edit {
+                            input.handle_key(event);
+                        }
+                    }
+                }
+                continue;
+            }
+            mode::Mode::Bookmarks(popup) => {
+                popup.error = None;
+                match event.code {
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('b') => { mode = mode::Mode::Normal }
+                    KeyCode::Down => { popup.next(app.bookmarks()) }
+                    KeyCode::Up => { popup.previous(app.bookmarks()) }
+                    KeyCode::Char('r') => popup.start_edit(app.bookmarks(), bookmarks::EditField::Name),
+                    KeyCode::Char('e') => popup.start_edit(app.bookmarks(), bookmarks::EditField::Path),
+                    KeyCode::Char('d') | KeyCode::Delete => {
+                        if let Some(name) = popup.selected(app.bookmarks()).map(|(n, _)| n.clone()) {
+                            if let Err(e) = app.bookmarks_mut().remove(&name) {
+                                popup.error = Some(format!("Could not save bookmarks: {}", e));
+                            }
+                            popup.clamp(app.bookmarks());
+                        }
+                    }
+                    KeyCode::Enter => {
+                        if let Some(target) = popup.selected(app.bookmarks()).map(|(_, p)| p.clone()) {
+                            match open_path(&mut app, &mut current_directory, &mut parent_directory, &target, None) {
+                                Ok(()) => {
+                                    mode = mode::Mode::Normal;
+                                    redraw_only = false;
+                                }
+                                Err(e) => popup.error = Some(format!("Can't open {}: {}", target.display(), e)),
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            mode::Mode::History(popup) => {
+                match event.code {
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('H') => { mode = mode::Mode::Normal }
+                    KeyCode::Down => { popup.next() }
+                    KeyCode::Up => { popup.previous() }
+                    KeyCode::Enter => {
+                        if let Some(target) = popup.selected().map(|p| p.to_path_buf()) {
+                            mode = mode::Mode::Normal;
+                            if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, &target, None) {
+                                ui_data.set_error(format!("Can't open {}: {}", target.display(), e));
+                            }
+                            redraw_only = false;
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            mode::Mode::Goto(prompt) => {
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Tab => prompt.complete(app.current_path(), app.shows_hidden()),
+                    KeyCode::BackTab | KeyCode::Up => prompt.cycle(-1),
+                    KeyCode::Down => prompt.cycle(1),
+                    KeyCode::Enter => {
+                        let target = goto::resolve(prompt.input.text(), app.current_path());
+                        match target {
+                            Ok(path) if path.is_dir() => {
+                                mode = mode::Mode::Normal;
+                                if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, &path, None) {
+                                    ui_data.set_error(format!("Can't open {}: {}", path.display(), e));
+                                }
+                                redraw_only = false;
+                            }
+                            Ok(path) if path.exists() => {
+                                // A file, open its directory with it selected
+                                mode = mode::Mode::Normal;
+                                if let Some(dir) = path.parent() {
+                                    if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, dir, Some(&path)) {
+                                        ui_data.set_error(format!("Can't open {}: {}", dir.display(), e));
+                                    }
+                                    redraw_only = false;
+                                }
+                            }
+                            Ok(path) => prompt.error = Some(format!("{} does not exist", path.display())),
+                            Err(e) => prompt.error = Some(e),
+                        }
+                    }
+                    _ => {
+                        if prompt.input.handle_key(event) {
+                            prompt.update(app.current_path(), app.shows_hidden());
+                        }
+                    }
+                }
+                continue;
+            }
+            mode::Mode::SelectGlob(input) => {
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Enter => {
+                        let pattern = input.text().to_string();
+                        let mut count = 0;
+                        for entry in current_directory.items() {
+                            let (pattern, name) = filter::fold_case(&pattern, &entry.name_lossy());
+                            if filter::glob_match(&pattern, &name).is_some() {
+                                app.selection_mut().insert(entry);
+                                count += 1;
+                            }
+                        }
+                        ui_data.set_message(format!("Selected {} matching '{}'", count, pattern));
+                        mode = mode::Mode::Normal;
+                    }
+                    _ => { input.handle_key(event); }
+                }
+                continue;
+            }
+            mode::Mode::Trash(view) if view.question.is_some() => {
+                if let KeyCode::Char('y') | KeyCode::Char('Y') = event.code {
+                    view.confirm();
+                } else {
+                    view.question = None;
+                }
+                continue;
+            }
+            mode::Mode::Trash(view) => {
+                view.error = None;
+                match event.code {
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('T') => {
+                        mode = mode::Mode::Normal;
+                        reload(&app, &mut current_directory, &mut parent_directory)?;
                         redraw_only = false;
-                    };
-                }
-                KeyCode::Right | KeyCode::Enter => {
-                    if let Some(idx) = current_directory.state.selected() {
-                        if let Ok(items) = app.down(idx) {
-                            parent_directory.set_items(items);
-                            parent_directory.select(Some(idx));
-                            current_directory.set_items(app.list_cwd_child_names()?);
-                            if let Some(idx) = app.pop_last_visited_idx() {
-                                current_directory.select(Some(idx));
+                    }
+                    KeyCode::Down => view.next(),
+                    KeyCode::Up => view.previous(),
+                    KeyCode::Right | KeyCode::Tab => view.cycle_trash(1),
+                    KeyCode::Left | KeyCode::BackTab => view.cycle_trash(-1),
+                    KeyCode::Enter | KeyCode::Char('r') => view.restore(),
+                    KeyCode::Char('d') | KeyCode::Char('D') | KeyCode::Delete => view.ask_purge(),
+                    _ => {}
+                }
+                continue;
+            }
+            mode::Mode::Create(prompt) => {
+                prompt.error = None;
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Tab => prompt.cycle_template(),
+                    KeyCode::Enter => match app.create(prompt) {
+                        Ok(path) => {
+                            mode = mode::Mode::Normal;
+                            let dir = path.parent().unwrap_or(&path);
+                            if dir == app.current_path() {
+                                reload(&app, &mut current_directory, &mut parent_directory)?;
+                                if !current_directory.select_path(&path) && current_directory.filter().is_some() {
+                                    current_directory.set_filter(None);
+                                    current_directory.select_path(&path);
+                                }
                             } else {
-                                current_directory.select(Some(0));
+                                let dir = dir.to_path_buf();
+                                open_path(&mut app, &mut current_directory, &mut parent_directory, &dir, Some(&path))?;
                             }
                             redraw_only = false;
                         }
+                        Err(e) => prompt.error = Some(e.to_string()),
+                    },
+                    _ => { prompt.input.handle_key(event); }
+                }
+                continue;
+            }
+            mode::Mode::Link(prompt) => {
+                prompt.error = None;
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Tab => prompt.cycle_kind(),
+                    KeyCode::Enter => {
+                        let linked = prompt.dir(app.current_path())
+                            .map_err(|e| e.into())
+                            .and_then(|dir| app.link(prompt.kind, &prompt.sources, &dir).map(|links| (dir, links)));
+                        match linked {
+                            Ok((dir, links)) => {
+                                ui_data.set_message(format!("Linked {} into {}", describe_paths(&prompt.sources), dir.display()));
+                                if links.len() == 1 {
+                                    open_path(&mut app, &mut current_directory, &mut parent_directory, &dir, links.first().map(|p| p.as_path()))?;
+                                } else {
+                                    reload(&app, &mut current_directory, &mut parent_directory)?;
+                                }
+                                app.selection_mut().clear();
+                                mode = mode::Mode::Normal;
+                                redraw_only = false;
+                            }
+                            Err(e) => prompt.error = Some(e.to_string()),
+                        }
+                    }
+                    _ => { prompt.input.handle_key(event); }
+                }
+                continue;
+            }
+            mode::Mode::Properties(props) => {
+                let editing = props.input_mut().is_some();
+                match (event.code, props.field) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('q'), _) | (KeyCode::Char('i'), _) if !editing || event.code == KeyCode::Esc => {
+                        mode = mode::Mode::Normal;
+                        reload(&app, &mut current_directory, &mut parent_directory)?;
+                        redraw_only = false;
+                    }
+                    (KeyCode::Tab, _) | (KeyCode::BackTab, _) => {
+                        props.sync_octal();
+                        props.cycle_field(if event.code == KeyCode::Tab { 1 } else { -1 });
+                    }
+                    (KeyCode::Enter, _) => {
+                        if let Some(change) = props.apply() {
+                            app.jobs_mut().push_change(props.path.clone(), change);
+                            mode = mode::Mode::Normal;
+                        }
+                        redraw_only = false;
+                    }
+                    (KeyCode::Up, props::PropField::Bits) => props.move_bit(-1, 0),
+                    (KeyCode::Down, props::PropField::Bits) => props.move_bit(1, 0),
+                    (KeyCode::Left, props::PropField::Bits) => props.move_bit(0, -1),
+                    (KeyCode::Right, props::PropField::Bits) => props.move_bit(0, 1),
+                    (KeyCode::Char(' '), props::PropField::Bits) | (KeyCode::Char('x'), props::PropField::Bits) => props.toggle_bit(),
+                    (KeyCode::Char(' '), props::PropField::Recursive) => props.recursive = !props.recursive,
+                    (_, field) => {
+                        if let Some(input) = props.input_mut() {
+                            input.handle_key(event);
+                            if field == props::PropField::Octal {
+                                props.octal_changed();
+                            }
+                        }
                     }
                 }
-                _ => {}
+                continue;
+            }
+            mode::Mode::Rename(rename) => {
+                rename.error = None;
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Enter => {
+                        match rename.target() {
+                            Ok(Some(target)) => match app.rename(&[(rename.path.clone(), target.clone())]) {
+                                Ok(()) => {
+                                    mode = mode::Mode::Normal;
+                                    reload(&app, &mut current_directory, &mut parent_directory)?;
+                                    if !current_directory.select_path(&target) && current_directory.filter().is_some() {
+                                        // The filter would hide the renamed entry
+                                        current_directory.set_filter(None);
+                                        current_directory.select_path(&target);
+                                    }
+                                    redraw_only = false;
+                                }
+                                Err(e) => rename.error = Some(e.to_string()),
+                            },
+                            Ok(None) => { mode = mode::Mode::Normal }
+                            Err(e) => rename.error = Some(e),
+                        }
+                    }
+                    _ => { rename.input.handle_key(event); }
+                }
+                continue;
+            }
+            mode::Mode::BulkRename(preview) => {
+                match event.code {
+                    KeyCode::Down => preview.scroll_by(1),
+                    KeyCode::Up => preview.scroll_by(-1),
+                    KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                        let count = preview.pairs.len();
+                        match app.rename(&preview.pairs) {
+                            Ok(()) => ui_data.set_message(format!("Renamed {} entries", count)),
+                            Err(e) => ui_data.set_error(format!("Renaming failed: {}", e)),
+                        }
+                        mode = mode::Mode::Normal;
+                        reload(&app, &mut current_directory, &mut parent_directory)?;
+                        redraw_only = false;
+                    }
+                    _ => { mode = mode::Mode::Normal }
+                }
+                continue;
+            }
+            mode::Mode::BatchRename(batch) => {
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Tab | KeyCode::BackTab => batch.switch_field(),
+                    KeyCode::Down => batch.scroll_by(1),
+                    KeyCode::Up => batch.scroll_by(-1),
+                    KeyCode::PageDown => batch.scroll_by(10),
+                    KeyCode::PageUp => batch.scroll_by(-10),
+                    KeyCode::Enter => {
+                        match batch.pairs() {
+                            Some(pairs) if pairs.is_empty() => ui_data.set_message("Nothing was renamed".to_string()),
+                            Some(pairs) => match app.rename(&pairs) {
+                                Ok(()) => ui_data.set_message(format!("Renamed {} entries", pairs.len())),
+                                Err(e) => ui_data.set_error(format!("Renaming failed: {}", e)),
+                            },
+                            // The problems are shown in the dialog
+                            None => continue,
+                        }
+                        mode = mode::Mode::Normal;
+                        reload(&app, &mut current_directory, &mut parent_directory)?;
+                        redraw_only = false;
+                    }
+                    _ => {
+                        if batch.input_mut().handle_key(event) {
+                            batch.update();
+                        }
+                    }
+                }
+                continue;
+            }
+            mode::Mode::Conflict(dialog) => {
+                let choice = match event.code {
+                    KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.jobs_mut().cancel_current();
+                        mode = mode::Mode::Normal;
+                        continue;
+                    }
+                    KeyCode::Down | KeyCode::Tab | KeyCode::Char('j') => { dialog.next(); None }
+                    KeyCode::Up | KeyCode::BackTab | KeyCode::Char('k') => { dialog.previous(); None }
+                    KeyCode::Char('a') | KeyCode::Char(' ') => { dialog.all = !dialog.all; None }
+                    KeyCode::Enter => Some(mode::ConflictDialog::CHOICES[dialog.selected].0),
+                    KeyCode::Esc => Some(jobs::Choice::Skip),
+                    KeyCode::Char(c) => mode::ConflictDialog::CHOICES.iter().find(|(_, key, _)| *key == c).map(|(choice, _, _)| *choice),
+                    _ => None,
+                };
+                // Only once a conflict arrived, not while comparing
+                if let Some(choice) = choice.filter(|_| app.jobs().conflict().is_some()) {
+                    app.jobs_mut().resolve(choice, dialog.all);
+                    if choice != jobs::Choice::Compare {
+                        mode = mode::Mode::Normal;
+                    }
+                }
+                continue;
+            }
+            mode::Mode::Confirm(confirm) => {
+                if let KeyCode::Char('y') | KeyCode::Char('Y') = event.code {
+                    match &mut confirm.action {
+                        mode::Action::Delete(paths) => {
+                            app.delete(std::mem::take(paths));
+                            app.selection_mut().clear();
+                        }
+                        mode::Action::Quit => break,
+                    }
+                }
+                mode = mode::Mode::Normal;
+                continue;
+            }
+            mode::Mode::Pager(pager) => {
+                if let Some((_, input)) = &mut pager.prompt {
+                    match event.code {
+                        KeyCode::Esc => pager.prompt = None,
+                        KeyCode::Enter => pager.submit(),
+                        _ => { input.handle_key(event); }
+                    }
+                    continue;
+                }
+                pager.message = None;
+                match event.code {
+                    KeyCode::Esc if pager.is_searching() => pager.cancel_search(),
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('v') => { mode = mode::Mode::Normal }
+                    KeyCode::Down | KeyCode::Char('j') | KeyCode::Enter => pager.scroll(1),
+                    KeyCode::Up | KeyCode::Char('k') => pager.scroll(-1),
+                    KeyCode::PageDown | KeyCode::Char(' ') | KeyCode::Char('f') => pager.scroll(pager.page()),
+                    KeyCode::PageUp | KeyCode::Char('b') => pager.scroll(-pager.page()),
+                    KeyCode::Home | KeyCode::Char('g') => pager.go_to(0),
+                    KeyCode::End | KeyCode::Char('G') => pager.go_to_end(),
+                    KeyCode::Left | KeyCode::Char('h') => pager.scroll_columns(false),
+                    KeyCode::Right | KeyCode::Char('l') => pager.scroll_columns(true),
+                    KeyCode::Char('w') => pager.wrap = !pager.wrap,
+                    KeyCode::Char('#') => pager.numbers = !pager.numbers,
+                    KeyCode::Char('/') => pager.open_prompt(pager::PagerPrompt::Search),
+                    KeyCode::Char(':') => pager.open_prompt(pager::PagerPrompt::Line),
+                    KeyCode::Char('n') => pager.search_again(true),
+                    KeyCode::Char('N') => pager.search_again(false),
+                    _ => {}
+                }
+                continue;
+            }
+            mode::Mode::Finder(finder) => {
+                match event.code {
+                    KeyCode::Esc => { mode = mode::Mode::Normal }
+                    KeyCode::Down => { finder.next() }
+                    KeyCode::Up => { finder.previous() }
+                    KeyCode::Enter => {
+                        if let Some(target) = finder.selected().map(|f| f.path.clone()) {
+                            mode = mode::Mode::Normal;
+                            if let Some(dir) = target.parent() {
+                                if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, dir, Some(&target)) {
+                                    ui_data.set_error(format!("Can't open {}: {}", dir.display(), e));
+                                }
+                                redraw_only = false;
+                            }
+                        }
+                    }
+                    _ => {
+                        if finder.input.handle_key(event) {
+                            finder.rank();
+                        }
+                    }
+                }
+                continue;
+            }
+        }
+        ui_data.clear_message();
+        if let Some(prefix) = pending.take() {
+            if let KeyCode::Char(c) = event.code {
+                match prefix {
+                    'o' if change_sort(&mut app, c) => {
+                        reload(&app, &mut current_directory, &mut parent_directory)?;
+                        redraw_only = false;
+                    }
+                    'm' => {
+                        let cwd = app.current_path().to_path_buf();
+                        match app.bookmarks_mut().set(&c.to_string(), &cwd) {
+                            Ok(()) => ui_data.set_message(format!("Bookmarked {} as '{}'", cwd.display(), c)),
+                            Err(e) => ui_data.set_error(format!("Could not save bookmarks: {}", e)),
+                        }
+                    }
+                    '\'' => {
+                        match app.bookmarks().get(&c.to_string()).map(|p| p.to_path_buf()) {
+                            Some(target) => {
+                                match open_path(&mut app, &mut current_directory, &mut parent_directory, &target, None) {
+                                    Ok(()) => redraw_only = false,
+                                    Err(e) => ui_data.set_error(format!("Can't open {}: {}", target.display(), e)),
+                                }
+                            }
+                            None => ui_data.set_error(format!("No bookmark '{}'", c)),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        match event.code {
+            KeyCode::Esc if current_directory.filter().is_some() => {
+                current_directory.set_filter(None);
+            }
+            KeyCode::Esc if !app.selection().is_empty() => {
+                app.selection_mut().clear();
+            }
+            KeyCode::Char('q') | KeyCode::Esc if app.jobs().is_busy() => {
+                mode = mode::Mode::Confirm(mode::Confirm {
+                    question: "Cancel the running jobs and quit?".to_string(),
+                    action: mode::Action::Quit,
+                });
+            }
+            KeyCode::Char('q') | KeyCode::Esc => { break }
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) && !app.jobs_mut().cancel_current() => {
+                ui_data.set_error("Nothing to cancel".to_string());
+            }
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {}
+            KeyCode::Char(' ') => {
+                if let Some(entry) = current_directory.selected_entry() {
+                    app.selection_mut().toggle(entry);
+                }
+                current_directory.next();
+            }
+            KeyCode::Char('a') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                for entry in current_directory.items() {
+                    app.selection_mut().insert(entry);
+                }
+            }
+            KeyCode::Char('*') => {
+                for entry in current_directory.items() {
+                    app.selection_mut().toggle(entry);
+                }
+            }
+            KeyCode::Char('+') => { mode = mode::Mode::SelectGlob(input::LineInput::new()) }
+            KeyCode::Char(c @ 'y') | KeyCode::Char(c @ 'x') => {
+                let targets = app.targets(current_directory.selected_entry());
+                if !targets.is_empty() {
+                    let verb = if c == 'x' { "Cut" } else { "Yanked" };
+                    ui_data.set_message(format!("{} {}, paste with p", verb, describe_paths(&targets)));
+                    app.yank(targets, c == 'x');
+                    app.selection_mut().clear();
+                }
+            }
+            KeyCode::Char('p') => {
+                if let Err(e) = app.paste() {
+                    ui_data.set_error(e.to_string());
+                }
+            }
+            KeyCode::Char('D') | KeyCode::Delete if event.modifiers.contains(KeyModifiers::SHIFT) || event.code == KeyCode::Char('D') => {
+                let targets = app.targets(current_directory.selected_entry());
+                if !targets.is_empty() {
+                    mode = mode::Mode::Confirm(mode::Confirm {
+                        question: format!("Permanently delete {}?", describe_paths(&targets)),
+                        action: mode::Action::Delete(targets),
+                    });
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                let targets = app.targets(current_directory.selected_entry());
+                if !targets.is_empty() {
+                    app.trash(targets);
+                    app.selection_mut().clear();
+                }
+            }
+            KeyCode::Char('r') | KeyCode::F(2) if !event.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(entry) = current_directory.selected_entry() {
+                    mode = mode::Mode::Rename(rename::Rename::new(&entry.path));
+                }
+            }
+            KeyCode::Char('E') => {
+                let sources = if app.selection().is_empty() {
+                    current_directory.items().iter().map(|e| e.path.clone()).collect()
+                } else {
+                    app.selection().paths()
+                };
+                match edit_names(&mut terminal, &input_pause, &sources) {
+                    Ok(pairs) if pairs.is_empty() => ui_data.set_message("Nothing was renamed".to_string()),
+                    Ok(pairs) => mode = mode::Mode::BulkRename(rename::RenamePreview::new(pairs)),
+                    Err(e) => ui_data.set_error(e.to_string()),
+                }
+                redraw_only = false;
+            }
+            KeyCode::Char('n') => { mode = mode::Mode::Create(create::CreatePrompt::new(create::CreateKind::File)) }
+            KeyCode::Char('N') => { mode = mode::Mode::Create(create::CreatePrompt::new(create::CreateKind::Directory)) }
+            KeyCode::Char('L') => {
+                let targets = app.targets(current_directory.selected_entry());
+                if !targets.is_empty() {
+                    let prompt = link::LinkPrompt::new(targets, app.history().previous_dir());
+                    mode = mode::Mode::Link(prompt);
+                }
+            }
+            KeyCode::Char('J') => {
+                if let Some(entry) = current_directory.selected_entry().filter(|e| e.link_target.is_some()) {
+                    match link::link_target(&entry.path) {
+                        Ok(target) if entry.broken => ui_data.set_error(format!("{} points to {}, which doesn't exist", entry.name_lossy(), target.display())),
+                        Ok(target) => {
+                            // Through any further links
+                            let target = fs::canonicalize(&target).unwrap_or(target);
+                            let dir = target.parent().unwrap_or(&target).to_path_buf();
+                            if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, &dir, Some(&target)) {
+                                ui_data.set_error(format!("Can't open {}: {}", dir.display(), e));
+                            }
+                            redraw_only = false;
+                        }
+                        Err(e) => ui_data.set_error(format!("{}: {}", entry.name_lossy(), e)),
+                    }
+                }
+            }
+            KeyCode::Char('i') => {
+                if let Some(entry) = current_directory.selected_entry() {
+                    match props::Properties::new(&entry.path) {
+                        Ok(props) => mode = mode::Mode::Properties(Box::new(props)),
+                        Err(e) => ui_data.set_error(format!("{}: {}", entry.name_lossy(), e)),
+                    }
+                }
+            }
+            KeyCode::Char('R') => {
+                let targets = app.targets(current_directory.selected_entry());
+                if !targets.is_empty() {
+                    mode = mode::Mode::BatchRename(rename::BatchRename::new(targets));
+                }
+            }
+            KeyCode::Char('u') => {
+                match app.undo() {
+                    Ok(message) => ui_data.set_message(message),
+                    Err(e) => ui_data.set_error(e.to_string()),
+                }
+                reload(&app, &mut current_directory, &mut parent_directory)?;
+                redraw_only = false;
+            }
+            KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                match app.redo() {
+                    Ok(message) => ui_data.set_message(message),
+                    Err(e) => ui_data.set_error(e.to_string()),
+                }
+                reload(&app, &mut current_directory, &mut parent_directory)?;
+                redraw_only = false;
+            }
+            KeyCode::Char('T') => { mode = mode::Mode::Trash(trash::TrashView::new()) }
+            KeyCode::Char('v') => {
+                if let Some(entry) = current_directory.selected_entry().filter(|e| !e.is_dir) {
+                    match pager::Pager::open(&entry.path) {
+                        Ok(pager) => mode = mode::Mode::Pager(Box::new(pager)),
+                        Err(e) => ui_data.set_error(format!("{}: {}", entry.name_lossy(), e)),
+                    }
+                }
+            }
+            KeyCode::Down if event.modifiers.contains(KeyModifiers::SHIFT) => ui_data.scroll_preview(1),
+            KeyCode::Up if event.modifiers.contains(KeyModifiers::SHIFT) => ui_data.scroll_preview(-1),
+            KeyCode::PageDown => ui_data.scroll_preview(ui_data.preview_page()),
+            KeyCode::PageUp => ui_data.scroll_preview(-ui_data.preview_page()),
+            KeyCode::Down => { current_directory.next() }
+            KeyCode::Up => { current_directory.previous() }
+            KeyCode::Char(c @ 'o') | KeyCode::Char(c @ 'm') | KeyCode::Char(c @ '\'') => { pending = Some(c) }
+            KeyCode::Char('b') => {
+                mode = mode::Mode::Bookmarks(bookmarks::BookmarkPopup::new(app.bookmarks()));
+            }
+            KeyCode::Char('/') => {
+                if current_directory.filter().is_none() {
+                    current_directory.set_filter(Some(filter::Filter::new()));
+                }
+                mode = mode::Mode::Filter;
+            }
+            KeyCode::Char('f') => {
+                mode = mode::Mode::Finder(finder::Finder::spawn(app.current_path(), app.shows_hidden(), app.is_dirs_only()));
+            }
+            KeyCode::Char(':') | KeyCode::Char('g') => {
+                let mut prompt = goto::GotoPrompt::new();
+                prompt.update(app.current_path(), app.shows_hidden());
+                mode = mode::Mode::Goto(prompt);
+            }
+            KeyCode::Char('z') => {
+                mode = mode::Mode::Jump(jump::JumpPrompt::new(app.jumps_mut()));
+            }
+            KeyCode::Char('.') => {
+                app.toggle_hidden();
+                reload(&app, &mut current_directory, &mut parent_directory)?;
+                redraw_only = false;
+            }
+            KeyCode::Left if event.modifiers.contains(KeyModifiers::ALT) => {
+                let selected = current_directory.selected_entry().map(|e| e.path.clone());
+                match app.back(selected.as_deref()) {
+                    Ok(select) => {
+                        if let Err(e) = show_cwd(&mut app, &mut current_directory, &mut parent_directory, select.as_deref()) {
+                            ui_data.set_error(format!("Can't open {}: {}", app.current_path().display(), e));
+                        }
+                        redraw_only = false;
+                    }
+                    Err(e) => ui_data.set_error(e.to_string()),
+                }
+            }
+            KeyCode::Right if event.modifiers.contains(KeyModifiers::ALT) => {
+                let selected = current_directory.selected_entry().map(|e| e.path.clone());
+                match app.forward(selected.as_deref()) {
+                    Ok(select) => {
+                        if let Err(e) = show_cwd(&mut app, &mut current_directory, &mut parent_directory, select.as_deref()) {
+                            ui_data.set_error(format!("Can't open {}: {}", app.current_path().display(), e));
+                        }
+                        redraw_only = false;
+                    }
+                    Err(e) => ui_data.set_error(e.to_string()),
+                }
+            }
+            KeyCode::Char('-') => {
+                match app.history().previous_dir().map(|p| p.to_path_buf()) {
+                    Some(target) => {
+                        let select = app.current_path().to_path_buf();
+                        if let Err(e) = open_path(&mut app, &mut current_directory, &mut parent_directory, &target, Some(&select)) {
+                            ui_data.set_error(format!("Can't open {}: {}", target.display(), e));
+                        }
+                        redraw_only = false;
+                    }
+                    None => ui_data.set_error("No previous directory".to_string()),
+                }
+            }
+            KeyCode::Char('H') => {
+                mode = mode::Mode::History(history::HistoryPopup::new(app.history()));
+            }
+            KeyCode::Left | KeyCode::Backspace => {
+                redraw_only = !go_up(&mut app, &mut current_directory, &mut parent_directory);
+            }
+            KeyCode::Right | KeyCode::Enter => {
+                redraw_only = !go_down(&mut app, &mut current_directory, &mut parent_directory)?;
             }
             _ => {}
         }
     }
+    app.jobs_mut().shutdown();
+    // What the cancelled jobs got done is still worth undoing later
+    app.poll_jobs();
     cleanup(&mut terminal)?;
     tx_stop_thread.send(())?;
     input_thread_handle.join().unwrap_or(());
+    app.remember_selection(current_directory.selected_entry().map(|e| e.path.as_path()));
+    if let Err(e) = app.save_state() {
+        eprintln!("Could not save state: {}", e);
+    }
     if cli.keep {
         crate::cwd::cwd_host(app.current_path())?;
     }
     Ok(())
 }
 
+/// Like `argh::from_env`, but lets a lone `-` through as the path,
+/// which argh would otherwise reject as an unknown option
+fn parse_cli() -> Cli {
+    let mut args: Vec<String> = std::env::args().collect();
+    let path = args.remove(0);
+    let cmd = Path::new(&path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(&path)
+        .to_string();
+    if let Some(i) = args.iter().take_while(|a| *a != "--").position(|a| a == "-") {
+        args.remove(i);
+        if !args.iter().any(|a| a == "--") {
+            args.push("--".to_string());
+        }
+        args.push("-".to_string());
+    }
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    Cli::from_args(&[&cmd], &args).unwrap_or_else(|early_exit| {
+        // Usage errors go to stderr, out of the way of `cd "$(pc -k)"`
+        std::process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            }
+            Err(()) => {
+                eprintln!("{}\nRun {} --help for more information.", early_exit.output, cmd);
+                1
+            }
+        })
+    })
+}
+
+/// Prints the best jump database match for `pc --query`, without starting the ui
+fn print_jump(query: &str) -> Result<(), Box<dyn Error>> {
+    let mut db = jump::JumpDb::load();
+    let words: Vec<&str> = query.split_whitespace().collect();
+    let best = db.query(&words).into_iter().next();
+    db.save()?;
+    match best {
+        Some(path) => {
+            println!("{}", path.display());
+            Ok(())
+        }
+        None => {
+            eprintln!("No directory matching '{}'", query);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Moves into the parent directory, returns whether it did
+fn go_up(app: &mut app::App, current: &mut ui::Folder, parent: &mut ui::Folder) -> bool {
+    let previous = app.current_path().to_path_buf();
+    if app.up(current.selected_entry()).is_err() {
+        return false;
+    }
+    current.set_items(parent.take_items());
+    if !current.select_path(&previous) {
+        current.select(Some(0));
+    }
+    parent.set_items(app.parent_children().unwrap_or_default());
+    parent.select(app.cwd_parent_idx(parent.items()));
+    true
+}
+
+/// Moves into the selected directory, returns whether it did
+fn go_down(app: &mut app::App, current: &mut ui::Folder, parent: &mut ui::Folder) -> Result<bool, Box<dyn Error>> {
+    let entry = match current.selected_entry() {
+        Some(entry) => entry.clone(),
+        None => return Ok(false),
+    };
+    if app.down(&entry).is_err() {
+        return Ok(false);
+    }
+    parent.set_items(current.take_items());
+    parent.select(app.cwd_parent_idx(parent.items()));
+    current.set_items(app.list_cwd_children()?);
+    let idx = app.remembered_idx(current.items());
+    current.select(Some(idx.unwrap_or(0)));
+    Ok(true)
+}
+
+/// Moves to `dir`, selecting `select` in it if given
+fn open_path(app: &mut app::App, current: &mut ui::Folder, parent: &mut ui::Folder, dir: &Path, select: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    app.jump(dir, current.selected_entry().map(|e| e.path.as_path()))?;
+    show_cwd(app, current, parent, select)
+}
+
+/// Lists the current directory and its parent after moving there, selecting `select` if given
+fn show_cwd(app: &mut app::App, current: &mut ui::Folder, parent: &mut ui::Folder, select: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    current.set_items(app.list_cwd_children()?);
+    parent.set_items(app.parent_children().unwrap_or_default());
+    parent.select(app.cwd_parent_idx(parent.items()));
+    if let Some(target) = select {
+        if !current.select_path(target) && current.filter().is_some() {
+            // The filter would hide what we came for
+            current.set_filter(None);
+            current.select_path(target);
+        }
+    }
+    if current.state.selected().is_none() {
+        current.select(Some(app.remembered_idx(current.items()).unwrap_or(0)));
+    }
+    Ok(())
+}
+
+/// Applies a key pressed after `o` to the sort order, returns whether anything changed
+fn change_sort(app: &mut app::App, key: char) -> bool {
+    use sort::SortKey;
+    let mut order = app.sort_order();
+    match key {
+        'n' => order.key = SortKey::Name,
+        'N' => order.key = SortKey::Natural,
+        'e' => order.key = SortKey::Extension,
+        's' => order.key = SortKey::Size,
+        'm' => order.key = SortKey::Modified,
+        'c' => order.key = SortKey::Changed,
+        'r' => order.reverse = !order.reverse,
+        'd' => order.dirs_first = !order.dirs_first,
+        'i' => order.case_sensitive = !order.case_sensitive,
+        _ => return false,
+    }
+    app.set_sort_order(order);
+    true
+}
+
+/// Lets the names of `sources` be edited in the user's editor, returning the renames to make
+fn edit_names<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, pause: &InputPause, sources: &[PathBuf]) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+    if sources.is_empty() {
+        return Err("Nothing to rename".into());
+    }
+    // Entries from several directories are listed by their paths from a directory holding them all
+    let parent = common_parent(sources);
+    let mut lines = vec![];
+    let mut dirs = vec![];
+    for source in sources {
+        let line = source.strip_prefix(&parent).ok().and_then(|p| p.to_str()).filter(|l| !l.contains('\n'));
+        let name = source.file_name().and_then(|n| n.to_str());
+        match (line, name) {
+            (Some(line), Some(name)) => {
+                dirs.push(line[..line.len() - name.len()].to_string());
+                lines.push(line.to_string());
+            }
+            _ => return Err(format!("{} can't be edited as a line of text", source.display()).into()),
+        }
+    }
+    let file = storage::write_temp("pc-rename", &(lines.join("\n") + "\n"))?;
+    let edited = run_editor(terminal, pause, &file).and_then(|_| Ok(fs::read_to_string(&file)?));
+    fs::remove_file(&file).ok();
+    let mut names: Vec<String> = edited?.lines().map(|l| l.to_string()).collect();
+    if names.len() == sources.len() {
+        for (name, dir) in names.iter_mut().zip(&dirs) {
+            match name.strip_prefix(dir.as_str()) {
+                Some(rest) => *name = rest.to_string(),
+                None => return Err(format!("'{}': only names can be changed, not the directories before them", name).into()),
+            }
+        }
+    }
+    Ok(rename::plan(sources, &names)?)
+}
+
+/// The deepest directory holding all of `paths`
+fn common_parent(paths: &[PathBuf]) -> PathBuf {
+    let mut parent = paths[0].parent().map(Path::to_path_buf).unwrap_or_default();
+    for path in &paths[1..] {
+        while !path.starts_with(&parent) && parent.pop() {}
+    }
+    parent
+}
+
+/// Hands the terminal over to `$VISUAL` or `$EDITOR` editing `file`, taking it back afterwards
+fn run_editor<B: Backend + std::io::Write>(terminal: &mut Terminal<B>, pause: &InputPause, file: &Path) -> Result<(), Box<dyn Error>> {
+    let editor = std::env::var("VISUAL").ok().filter(|e| !e.trim().is_empty())
+        .or_else(|| std::env::var("EDITOR").ok().filter(|e| !e.trim().is_empty()))
+        .unwrap_or_else(|| if cfg!(windows) { "notepad".to_string() } else { "vi".to_string() });
+    // Allow for editors given with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words).arg(file);
+    #[cfg(unix)]
+    {
+        // Standard output may be a pipe, as for `cd "$(pc -k)"`, while the editor needs the terminal
+        if let Ok(tty) = fs::OpenOptions::new().read(true).write(true).open("/dev/tty") {
+            command.stdin(tty.try_clone()?).stdout(tty);
+        }
+    }
+
+    pause.pause();
+    cleanup(terminal)?;
+    let status = command.status();
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal.clear()?;
+    pause.resume();
+
+    let status = status.map_err(|e| format!("Could not run {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}, nothing was renamed", editor, status).into());
+    }
+    Ok(())
+}
+
+/// The name of a single path, or how many there are
+fn describe_paths(paths: &[std::path::PathBuf]) -> String {
+    match paths {
+        [single] => single.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| single.display().to_string()),
+        all => format!("{} items", all.len()),
+    }
+}
+
+/// Tells how a finished copy, move or delete went
+fn report_job(ui_data: &mut ui::UiData, outcome: &jobs::Outcome) {
+    let files = if outcome.files == 1 { "1 item".to_string() } else { format!("{} items", outcome.files) };
+    let mut done = outcome.summary.clone().unwrap_or_else(|| format!("{} {}", outcome.kind.past(), files));
+    if outcome.skipped > 0 {
+        done.push_str(&format!(", skipped {}", outcome.skipped));
+    }
+    match outcome.errors.as_slice() {
+        [] if outcome.cancelled => ui_data.set_message(format!("Cancelled, {}", done.to_lowercase())),
+        [] => ui_data.set_message(done),
+        [first] => ui_data.set_error(format!("{}, failed: {}", done, first)),
+        [first, rest @ ..] => ui_data.set_error(format!("{}, {} failed: {} and {} more", done, rest.len() + 1, first, rest.len())),
+    }
+}
+
+/// Re-reads both directory columns, keeping the selection on the same entries
+fn reload(app: &app::App, current: &mut ui::Folder, parent: &mut ui::Folder) -> Result<(), Box<dyn Error>> {
+    let selected = current.selected_entry().map(|e| e.path.clone());
+    current.set_items(app.list_cwd_children()?);
+    if !selected.is_some_and(|p| current.select_path(&p)) {
+        current.select(Some(0));
+    }
+    parent.set_items(app.parent_children().unwrap_or_default());
+    parent.select(app.cwd_parent_idx(parent.items()));
+    Ok(())
+}
+
 #[cfg(not(windows))]
 fn cleanup<B: Backend + std::io::Write>(terminal: &mut Terminal<B>) -> Result<(), Box<dyn Error>> {
     disable_raw_mode()?;
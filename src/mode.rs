@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+use crate::{bookmarks::BookmarkPopup, jobs::Choice, link::LinkPrompt, pager::Pager, props::Properties, create::CreatePrompt, finder::Finder, goto::GotoPrompt, history::HistoryPopup, input::LineInput, jump::JumpPrompt, rename::{BatchRename, Rename, RenamePreview}, trash::TrashView};
+
+/// What the keyboard input is currently driving
+pub enum Mode {
+    /// Moving around in the directory columns
+    Normal,
+    /// Typing into the filter of the current directory
+    Filter,
+    /// The recursive file finder overlay
+    Finder(Finder),
+    /// Jumping to a frequently visited directory
+    Jump(JumpPrompt),
+    /// The list of bookmarks
+    Bookmarks(BookmarkPopup),
+    /// The list of recently visited directories
+    History(HistoryPopup),
+    /// Typing a path to go to
+    Goto(GotoPrompt),
+    /// Typing a glob pattern of names to select in the current directory
+    SelectGlob(LineInput),
+    /// Typing the path of a new file or directory
+    Create(CreatePrompt),
+    /// Typing the directory to link the selected entries into
+    Link(LinkPrompt),
+    /// Editing the name of the selected entry
+    Rename(Rename),
+    /// Confirming renames made in the editor
+    BulkRename(RenamePreview),
+    /// Renaming the selected entries by a regex and a template
+    BatchRename(BatchRename),
+    /// Details of the selected entry, editing its permissions and ownership
+    Properties(Box<Properties>),
+    /// Reading the selected file over the whole screen
+    Pager(Box<Pager>),
+    /// Browsing the trash
+    Trash(TrashView),
+    /// Waiting for a yes or no before doing something drastic
+    Confirm(Confirm),
+    /// A copy or move waits for what to do about an existing target
+    Conflict(ConflictDialog),
+}
+
+/// Choosing what to do about a copy or move target which already exists,
+/// the conflict itself is kept by the jobs
+#[derive(Default)]
+pub struct ConflictDialog {
+    /// Index into `CHOICES`
+    pub selected: usize,
+    /// Use the choice for the rest of the job
+    pub all: bool,
+}
+impl ConflictDialog {
+    pub const CHOICES: [(Choice, char, &'static str); 5] = [
+        (Choice::Overwrite, 'o', "Overwrite"),
+        (Choice::OverwriteIfNewer, 'n', "Overwrite if newer"),
+        (Choice::Rename, 'r', "Rename with a suffix"),
+        (Choice::Skip, 's', "Skip"),
+        (Choice::Compare, 'c', "Compare contents"),
+    ];
+
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1) % Self::CHOICES.len();
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = (self.selected + Self::CHOICES.len() - 1) % Self::CHOICES.len();
+    }
+}
+
+/// Something to do once the user agrees to it
+pub enum Action {
+    Delete(Vec<PathBuf>),
+    /// Quit, cancelling the running jobs
+    Quit,
+}
+
+pub struct Confirm {
+    pub question: String,
+    pub action: Action,
+}
@@ -0,0 +1,526 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+use content_inspector::{inspect, ContentType};
+use crate::{hexdump::BYTES_PER_ROW, input::LineInput, magic, preview::{read_line, SNIFF_BYTES}};
+
+/// Lines are cut after this many bytes, for viewing and for searching
+const MAX_LINE_BYTES: usize = 64 * 1024;
+/// The indexer notes where a line starts after this many lines,
+const CHECKPOINT_LINES: usize = 64;
+/// or after this many bytes, so any line is a short read away from the note before it
+const CHECKPOINT_BYTES: u64 = 1024 * 1024;
+/// The indexer reports its progress after reading this many bytes
+const REPORT_BYTES: u64 = 4 * 1024 * 1024;
+const TAB_WIDTH: usize = 4;
+/// Columns moved by Left and Right
+const SCROLL_COLUMNS: usize = 8;
+
+/// Progress of the indexer thread
+struct Indexed {
+    checkpoints: Vec<(usize, u64)>,
+    lines: usize,
+    done: bool,
+}
+
+/// A search running on its own thread, finding the line number of a match
+struct Search {
+    rx: Receiver<Option<usize>>,
+    cancel: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PagerPrompt {
+    Search,
+    Line,
+}
+
+/// Full screen view of a file. Lines are read as they come into view, by their offsets
+/// which an indexer thread finds, so huge files open at once. Binary files are shown as
+/// a hex dump, where the lines are rows of bytes.
+pub struct Pager {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    hex: bool,
+    /// What a binary file is, when its signature is known
+    file_type: Option<&'static str>,
+    /// Line numbers with the offset each starts at, every `CHECKPOINT_LINES` lines or
+    /// `CHECKPOINT_BYTES` bytes
+    checkpoints: Vec<(usize, u64)>,
+    /// Lines indexed so far
+    lines: usize,
+    indexed: bool,
+    indexer: Receiver<Indexed>,
+    cancel: Arc<AtomicBool>,
+    /// The first line shown
+    top: usize,
+    /// Columns scrolled to the right, without wrapping
+    left: usize,
+    height: usize,
+    pub wrap: bool,
+    pub numbers: bool,
+    /// Lines from `top` on, with tabs expanded
+    view: Vec<String>,
+    /// The first line and the number of lines in `view`, and how many were indexed then
+    view_key: Option<(usize, usize, usize)>,
+    /// A line to go to once the indexer gets there, `usize::MAX` for the end
+    pending: Option<usize>,
+    query: Option<String>,
+    /// The line of the last match found
+    found: Option<usize>,
+    search: Option<Search>,
+    pub prompt: Option<(PagerPrompt, LineInput)>,
+    pub message: Option<String>,
+}
+impl Pager {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        if !meta.is_file() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a regular file"));
+        }
+        let mut file = File::open(path)?;
+        let mut head = Vec::new();
+        (&mut file).take(SNIFF_BYTES).read_to_end(&mut head)?;
+        let hex = inspect(&head) == ContentType::BINARY;
+        let (tx, indexer) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        if !hex {
+            let index_cancel = cancel.clone();
+            // Its own handle, the offset of a cloned one would move under the view
+            let index_file = File::open(path)?;
+            thread::spawn(move || index(index_file, tx, index_cancel));
+        }
+        Ok(Pager {
+            path: path.to_path_buf(),
+            file,
+            size: meta.len(),
+            hex,
+            file_type: if hex { magic::file_type(&head) } else { None },
+            checkpoints: vec![(0, 0)],
+            // The rows of a dump are known from the size
+            lines: if hex { meta.len().div_ceil(BYTES_PER_ROW as u64) as usize } else { 0 },
+            indexed: hex,
+            indexer,
+            cancel,
+            top: 0,
+            left: 0,
+            height: 1,
+            wrap: false,
+            numbers: true,
+            view: vec![],
+            view_key: None,
+            pending: None,
+            query: None,
+            found: None,
+            search: None,
+            prompt: None,
+            message: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.hex
+    }
+
+    pub fn file_type(&self) -> Option<&'static str> {
+        self.file_type
+    }
+
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    pub fn left(&self) -> usize {
+        self.left
+    }
+
+    /// Lines in the file, and whether that's all of them or the indexer is still counting
+    pub fn lines(&self) -> (usize, bool) {
+        (self.lines, self.indexed)
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Picks up the progress of the indexer and the result of a search
+    pub fn poll(&mut self) {
+        while let Ok(indexed) = self.indexer.try_recv() {
+            self.checkpoints.extend(indexed.checkpoints);
+            self.lines = indexed.lines;
+            self.indexed = indexed.done;
+        }
+        if let Some(target) = self.pending {
+            if target < self.lines || self.indexed {
+                self.pending = None;
+                self.go_to(target);
+            }
+        }
+        let found = match &self.search {
+            Some(search) => match search.rx.try_recv() {
+                Ok(found) => found,
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => None,
+            },
+            None => return,
+        };
+        self.search = None;
+        self.found = found;
+        match found {
+            Some(line) => self.go_to(line),
+            None => self.message = Some(format!("Pattern not found: {}", self.query.as_deref().unwrap_or_default())),
+        }
+    }
+
+    /// Sets the number of lines a page has, as drawn
+    pub fn set_height(&mut self, height: usize) {
+        self.height = height.max(1);
+    }
+
+    pub fn page(&self) -> isize {
+        self.height as isize
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.saturating_sub(self.height)
+    }
+
+    pub fn scroll(&mut self, delta: isize) {
+        self.pending = None;
+        self.top = self.top.saturating_add_signed(delta).min(self.max_top());
+    }
+
+    pub fn scroll_columns(&mut self, right: bool) {
+        self.left = if right { self.left + SCROLL_COLUMNS } else { self.left.saturating_sub(SCROLL_COLUMNS) };
+    }
+
+    /// Shows `line` at the top, or the end when it's past it. Waits for the indexer when it
+    /// didn't get there yet.
+    pub fn go_to(&mut self, line: usize) {
+        if line >= self.lines && !self.indexed {
+            self.pending = Some(line);
+        }
+        self.top = line.min(self.max_top());
+    }
+
+    pub fn go_to_end(&mut self) {
+        self.go_to(usize::MAX);
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn open_prompt(&mut self, kind: PagerPrompt) {
+        if kind == PagerPrompt::Search && self.hex {
+            self.message = Some("Binary files can't be searched".to_string());
+            return;
+        }
+        self.message = None;
+        self.prompt = Some((kind, LineInput::new()));
+    }
+
+    /// Searches for or goes to what was typed into the prompt
+    pub fn submit(&mut self) {
+        let (kind, input) = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let text = input.text();
+        match kind {
+            PagerPrompt::Search if text.is_empty() => {}
+            PagerPrompt::Search => {
+                self.query = Some(text.to_string());
+                self.start_search(true, self.top);
+            }
+            PagerPrompt::Line if self.hex => match parse_offset(text.trim()) {
+                Some(offset) => self.go_to((offset / BYTES_PER_ROW as u64) as usize),
+                None => self.message = Some(format!("Not an offset: {}", text)),
+            },
+            PagerPrompt::Line => match text.trim().parse::<usize>() {
+                Ok(line) => self.go_to(line.saturating_sub(1)),
+                Err(_) => self.message = Some(format!("Not a line number: {}", text)),
+            },
+        }
+    }
+
+    /// Looks for the next or previous match of the last search
+    pub fn search_again(&mut self, forward: bool) {
+        if self.hex {
+            return;
+        }
+        if self.query.is_none() {
+            self.message = Some("Nothing searched for yet, use /".to_string());
+            return;
+        }
+        // From the last match while it's on the page, the top may not reach it near the end
+        let at = self.found
+            .filter(|line| (self.top..self.top + self.height).contains(line))
+            .unwrap_or(self.top);
+        self.start_search(forward, if forward { at + 1 } else { at });
+    }
+
+    /// Searches from line `from` on, or backwards from before it
+    fn start_search(&mut self, forward: bool, from: usize) {
+        self.cancel_search();
+        self.message = None;
+        let query = self.query.clone().unwrap_or_default();
+        let i = self.checkpoints.partition_point(|&(line, _)| line <= from) - 1;
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let search_cancel = cancel.clone();
+        let path = self.path.clone();
+        let checkpoints = if forward { vec![self.checkpoints[i]] } else { self.checkpoints[..=i].to_vec() };
+        thread::spawn(move || {
+            let found = if forward {
+                search(&path, &query, checkpoints[0], from..usize::MAX, false, &search_cancel)
+            } else {
+                search_back(&path, &query, &checkpoints, from, &search_cancel)
+            };
+            tx.send(found.unwrap_or(None)).ok();
+        });
+        self.search = Some(Search { rx, cancel });
+    }
+
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The lines on the page, reading them when the page moved
+    pub fn visible(&mut self) -> io::Result<&[String]> {
+        let count = self.height.min(self.lines.saturating_sub(self.top));
+        let key = (self.top, count, self.lines);
+        if self.view_key != Some(key) {
+            self.view_key = None;
+            let starts = self.starts(self.top, count)?;
+            self.view = starts.into_iter()
+                .map(|offset| self.read_at(offset))
+                .collect::<io::Result<_>>()?;
+            self.view_key = Some(key);
+        }
+        Ok(&self.view)
+    }
+
+    /// Where `count` lines from `from` on start. Each one is found from the checkpoint before
+    /// it, without reading through the long line that may end the stretch up to the next one.
+    fn starts(&self, from: usize, count: usize) -> io::Result<Vec<u64>> {
+        let end = (from + count).min(self.lines);
+        let mut starts = Vec::with_capacity(count);
+        let mut line = from;
+        let mut buf = Vec::new();
+        while line < end {
+            let i = self.checkpoints.partition_point(|&(l, _)| l <= line) - 1;
+            let (mut at, mut offset) = self.checkpoints[i];
+            let next = self.checkpoints.get(i + 1).map_or(usize::MAX, |c| c.0);
+            let mut reader = BufReader::new(&self.file);
+            reader.seek(SeekFrom::Start(offset))?;
+            while at < end {
+                if at >= line {
+                    starts.push(offset);
+                }
+                if at + 1 == next || at + 1 == end {
+                    at += 1;
+                    break;
+                }
+                buf.clear();
+                offset += reader.read_until(b'\n', &mut buf)? as u64;
+                at += 1;
+            }
+            line = at;
+        }
+        Ok(starts)
+    }
+
+    /// The bytes on the page of a hex dump, with the offset of the first one
+    pub fn dump(&self) -> io::Result<(u64, Vec<u8>)> {
+        let offset = (self.top * BYTES_PER_ROW) as u64;
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::new();
+        file.take((self.height * BYTES_PER_ROW) as u64).read_to_end(&mut bytes)?;
+        Ok((offset, bytes))
+    }
+
+    fn read_at(&self, offset: u64) -> io::Result<String> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        // A little more than the limit, for the line ending
+        let mut reader = BufReader::new(file.take(MAX_LINE_BYTES as u64 + 2));
+        let line = read_line(&mut reader, MAX_LINE_BYTES)?.map(|(line, _)| line).unwrap_or_default();
+        Ok(expand_tabs(&line))
+    }
+}
+impl Drop for Pager {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.cancel_search();
+    }
+}
+
+fn expand_tabs(line: &str) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = TAB_WIDTH - column % TAB_WIDTH;
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+/// A byte offset, in hex with a leading `0x` or else decimal
+fn parse_offset(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Char ranges of `query` in `line`, ignoring case unless the query has capitals.
+/// Chars are compared one by one so the ranges count chars of the line itself,
+/// since lowercasing the whole line can change how many there are
+pub fn match_ranges(query: &str, line: &str) -> Vec<(usize, usize)> {
+    if query.is_empty() {
+        return vec![];
+    }
+    let sensitive = query.chars().any(char::is_uppercase);
+    let same = |q: char, c: char| q == c || (!sensitive && q.to_lowercase().eq(c.to_lowercase()));
+    let query: Vec<char> = query.chars().collect();
+    let line: Vec<char> = line.chars().collect();
+    let mut ranges = vec![];
+    let mut start = 0;
+    while start + query.len() <= line.len() {
+        if query.iter().zip(&line[start..]).all(|(&q, &c)| same(q, c)) {
+            ranges.push((start, start + query.len()));
+            start += query.len();
+        } else {
+            start += 1;
+        }
+    }
+    ranges
+}
+
+/// Counts the lines of the file noting checkpoints, until it's done or cancelled
+fn index(mut file: File, tx: Sender<Indexed>, cancel: Arc<AtomicBool>) {
+    let mut buf = vec![0; 64 * 1024];
+    let mut offset = 0;
+    let mut lines = 0;
+    let mut last = (0, 0);
+    let mut checkpoints = vec![];
+    let mut line_starts = true;
+    let mut reported = 0;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let chunk = &buf[..n];
+        let mut i = 0;
+        loop {
+            if line_starts {
+                line_starts = false;
+                let at = offset + i as u64;
+                if lines - last.0 >= CHECKPOINT_LINES || at - last.1 >= CHECKPOINT_BYTES {
+                    last = (lines, at);
+                    checkpoints.push(last);
+                }
+                lines += 1;
+            }
+            match chunk[i..].iter().position(|&b| b == b'\n') {
+                Some(p) => {
+                    i += p + 1;
+                    line_starts = true;
+                    if i == n {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        offset += n as u64;
+        if offset - reported >= REPORT_BYTES {
+            reported = offset;
+            if tx.send(Indexed { checkpoints: std::mem::take(&mut checkpoints), lines, done: false }).is_err() {
+                return;
+            }
+        }
+    }
+    tx.send(Indexed { checkpoints, lines, done: true }).ok();
+}
+
+/// The last line before `from` matching `query`, going through the stretches between the
+/// checkpoints from the last one back
+fn search_back(path: &Path, query: &str, checkpoints: &[(usize, u64)], from: usize, cancel: &AtomicBool) -> io::Result<Option<usize>> {
+    for (k, &start) in checkpoints.iter().enumerate().rev() {
+        let end = checkpoints.get(k + 1).map_or(from, |c| c.0);
+        if let Some(line) = search(path, query, start, start.0..end, true, cancel)? {
+            return Ok(Some(line));
+        }
+    }
+    Ok(None)
+}
+
+/// The first line in `range` matching `query`, or the last one, reading from `start`
+fn search(path: &Path, query: &str, start: (usize, u64), range: std::ops::Range<usize>, last: bool, cancel: &AtomicBool) -> io::Result<Option<usize>> {
+    let sensitive = query.chars().any(char::is_uppercase);
+    let query = if sensitive { query.to_string() } else { query.to_lowercase() };
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start.1))?;
+    let mut reader = BufReader::new(file);
+    let mut found = None;
+    let mut line = start.0;
+    while line < range.end {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let text = match read_line(&mut reader, MAX_LINE_BYTES)? {
+            Some((text, _)) => text,
+            None => break,
+        };
+        let matched = if sensitive { text.contains(&query) } else { text.to_lowercase().contains(&query) };
+        if line >= range.start && matched {
+            found = Some(line);
+            if !last {
+                break;
+            }
+        }
+        line += 1;
+    }
+    Ok(found)
+}
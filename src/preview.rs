@@ -0,0 +1,219 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+use content_inspector::{inspect, ContentType};
+use tui::text::Spans;
+use crate::{highlight, hexdump, magic};
+
+/// Bytes looked at to tell text from binary files
+pub const SNIFF_BYTES: u64 = 512;
+/// Bytes read at most for a preview, as the ends of long lines are skipped over
+const SCAN_LIMIT: u64 = 64 * 1024 * 1024;
+
+struct Request {
+    id: u64,
+    path: PathBuf,
+    /// Lines, or rows of bytes, skipped from the top
+    scroll: usize,
+    rows: usize,
+    cols: usize,
+    cancel: Arc<AtomicBool>,
+}
+
+/// A part of a file from its top, or from where it's scrolled to, as much as fits the preview
+pub struct Preview {
+    id: u64,
+    pub path: PathBuf,
+    /// The lines skipped, less than asked for when the end was reached
+    pub scroll: usize,
+    /// The text, or a hex dump of a binary file, `None` when it can't be read
+    pub content: Option<Vec<Spans<'static>>>,
+    /// What a binary file is, when its signature is known
+    pub file_type: Option<&'static str>,
+}
+
+/// Reads file previews on a worker thread, so big or slow files don't hold up the ui
+pub struct Previewer {
+    tx: Sender<Request>,
+    rx: Receiver<Preview>,
+    last_id: u64,
+    cancel: Arc<AtomicBool>,
+}
+impl Previewer {
+    /// `wake` is called from the worker whenever a preview is ready
+    pub fn spawn<F: Fn() + Send + 'static>(wake: F) -> Self {
+        let (tx, requests) = mpsc::channel();
+        let (results, rx) = mpsc::channel();
+        thread::spawn(move || work(requests, results, wake));
+        Previewer { tx, rx, last_id: 0, cancel: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// Starts reading `path` for a preview of `rows` lines of `cols` characters below the first
+    /// `scroll` lines, dropping the preview asked for before
+    pub fn request(&mut self, path: &Path, scroll: usize, rows: usize, cols: usize) {
+        self.cancel.store(true, Ordering::SeqCst);
+        self.cancel = Arc::new(AtomicBool::new(false));
+        self.last_id += 1;
+        let request = Request { id: self.last_id, path: path.to_path_buf(), scroll, rows, cols, cancel: self.cancel.clone() };
+        self.tx.send(request).ok();
+    }
+
+    /// Drops the pending preview, if any
+    pub fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        self.last_id += 1;
+    }
+
+    /// The preview last asked for, once it's read
+    pub fn poll(&mut self) -> Option<Preview> {
+        let mut latest = None;
+        while let Ok(preview) = self.rx.try_recv() {
+            if preview.id == self.last_id {
+                latest = Some(preview);
+            }
+        }
+        latest
+    }
+}
+
+fn work<F: Fn()>(requests: Receiver<Request>, results: Sender<Preview>, wake: F) {
+    while let Ok(mut request) = requests.recv() {
+        // Only the newest request matters when the selection moved on meanwhile
+        while let Ok(newer) = requests.try_recv() {
+            request = newer;
+        }
+        let preview = read(&request).unwrap_or_else(|_| Preview {
+            id: request.id,
+            path: request.path.clone(),
+            scroll: request.scroll,
+            content: None,
+            file_type: None,
+        });
+        if request.cancel.load(Ordering::SeqCst) {
+            continue;
+        }
+        if results.send(preview).is_err() {
+            return;
+        }
+        wake();
+    }
+}
+
+/// Reads the `scroll` lines to skip and at most `rows` lines after them. A line is cut after the
+/// bytes `cols` characters can take, where it would overflow the preview anyway. A scroll past
+/// the end is taken back so the last lines fill the preview. Binary files are dumped in hex instead.
+fn read(request: &Request) -> io::Result<Preview> {
+    let mut preview = Preview { id: request.id, path: request.path.clone(), scroll: request.scroll, content: None, file_type: None };
+    // Opening a fifo would wait for a writer
+    let meta = fs::metadata(&request.path)?;
+    if !meta.is_file() {
+        return Ok(preview);
+    }
+    let size = meta.len();
+    let mut file = File::open(&request.path)?;
+    let mut head = Vec::new();
+    (&mut file).take(SNIFF_BYTES).read_to_end(&mut head)?;
+    if inspect(&head) == ContentType::BINARY {
+        preview.file_type = magic::file_type(&head);
+        let (scroll, content) = dump(request, &mut file, size)?;
+        preview.scroll = scroll;
+        preview.content = Some(content);
+        return Ok(preview);
+    }
+    let wanted = request.scroll + request.rows;
+    let max_line = request.cols * 4;
+    let mut reader = BufReader::new(io::Cursor::new(head).chain(file)).take(SCAN_LIMIT);
+    let mut lines = Vec::new();
+    let mut end = false;
+    while lines.len() < wanted {
+        if request.cancel.load(Ordering::SeqCst) {
+            return Ok(preview);
+        }
+        match read_line(&mut reader, max_line)? {
+            Some((line, _)) => lines.push(line),
+            None => {
+                // Running out of the scan limit isn't the end of the file
+                end = reader.limit() > 0;
+                break;
+            }
+        }
+    }
+    let scroll = if end { request.scroll.min(lines.len().saturating_sub(request.rows)) } else { request.scroll };
+    // Highlighting from the top keeps the parser state right for the lines shown
+    let mut content = highlight::highlight(&request.path, size, &lines)
+        .unwrap_or_else(|| lines.into_iter().map(Spans::from).collect());
+    content.drain(..scroll.min(content.len()));
+    preview.scroll = scroll;
+    preview.content = Some(content);
+    Ok(preview)
+}
+
+/// A hex dump of as many rows as fit, from the row scrolled to, read by its offset
+fn dump(request: &Request, file: &mut File, size: u64) -> io::Result<(usize, Vec<Spans<'static>>)> {
+    let digits = hexdump::offset_digits(size);
+    let width = hexdump::bytes_per_row(request.cols, digits);
+    let rows = size.div_ceil(width as u64) as usize;
+    let scroll = request.scroll.min(rows.saturating_sub(request.rows));
+    let offset = (scroll * width) as u64;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.take((request.rows * width) as u64).read_to_end(&mut bytes)?;
+    let content = bytes.chunks(width)
+        .enumerate()
+        .map(|(i, chunk)| hexdump::row(offset + (i * width) as u64, chunk, width, digits))
+        .collect();
+    Ok((scroll, content))
+}
+
+/// Reads a line of at most `max` bytes without its ending, the rest of a longer line is
+/// skipped. Returns the line with the number of bytes it took up, `None` at the end.
+pub fn read_line<R: BufRead>(reader: &mut R, max: usize) -> io::Result<Option<(String, u64)>> {
+    let mut bytes = Vec::new();
+    let mut consumed = 0;
+    let mut ended = false;
+    while !ended {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            if consumed == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+        let used = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                ended = true;
+                i + 1
+            }
+            None => buf.len(),
+        };
+        let room = max.saturating_sub(bytes.len());
+        bytes.extend_from_slice(&buf[..used.min(room)]);
+        reader.consume(used);
+        consumed += used as u64;
+    }
+    let cut = !ended || consumed > bytes.len() as u64;
+    if bytes.ends_with(b"\n") {
+        bytes.pop();
+        if bytes.ends_with(b"\r") {
+            bytes.pop();
+        }
+    }
+    Ok(Some((decode(&bytes, cut), consumed)))
+}
+
+/// A line that was cut short may end in the middle of a character, that part is dropped
+fn decode(bytes: &[u8], cut: bool) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(e) if cut && e.error_len().is_none() => String::from_utf8_lossy(&bytes[..e.valid_up_to()]).into_owned(),
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+use crate::input::LineInput;
+
+/// setuid, setgid and sticky, then read, write and execute for the owner, the group and others
+pub const BIT_ROWS: [(&str, [(u32, &str); 3]); 4] = [
+    ("special", [(0o4000, "setuid"), (0o2000, "setgid"), (0o1000, "sticky")]),
+    ("owner", [(0o400, "r"), (0o200, "w"), (0o100, "x")]),
+    ("group", [(0o040, "r"), (0o020, "w"), (0o010, "x")]),
+    ("others", [(0o004, "r"), (0o002, "w"), (0o001, "x")]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PropField {
+    Bits,
+    Octal,
+    Owner,
+    Group,
+    Recursive,
+}
+
+/// Details of an entry and the changes to its permissions and ownership being edited
+pub struct Properties {
+    pub path: PathBuf,
+    pub meta: Metadata,
+    pub link_target: Option<PathBuf>,
+    /// Permission bits as they are on disk
+    original_mode: u32,
+    pub mode: u32,
+    pub owner: LineInput,
+    pub group: LineInput,
+    original_owner: String,
+    original_group: String,
+    pub recursive: bool,
+    pub field: PropField,
+    /// Row and column in `BIT_ROWS` of the bit to toggle
+    pub bit: (usize, usize),
+    pub octal: LineInput,
+    /// How applying the changes went, and whether that was an error
+    pub result: Option<(String, bool)>,
+}
+impl Properties {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let meta = fs::symlink_metadata(path)?;
+        let link_target = fs::read_link(path).ok();
+        let mode = mode(&meta);
+        let (owner, group) = (owner_name(&meta), group_name(&meta));
+        let mut props = Properties {
+            path: path.to_path_buf(),
+            link_target,
+            original_mode: mode,
+            mode,
+            owner: LineInput::new(),
+            group: LineInput::new(),
+            original_owner: owner.clone(),
+            original_group: group.clone(),
+            recursive: false,
+            field: PropField::Bits,
+            bit: (1, 0),
+            octal: LineInput::new(),
+            result: None,
+            meta,
+        };
+        props.owner.set_text(&owner);
+        props.group.set_text(&group);
+        props.sync_octal();
+        Ok(props)
+    }
+
+    /// Whether the platform has owners, groups and the full set of mode bits
+    pub fn full() -> bool {
+        cfg!(unix)
+    }
+
+    pub fn fields(&self) -> Vec<PropField> {
+        let mut fields = vec![PropField::Bits, PropField::Octal];
+        if Self::full() {
+            fields.extend([PropField::Owner, PropField::Group]);
+        }
+        if self.meta.is_dir() {
+            fields.push(PropField::Recursive);
+        }
+        fields
+    }
+
+    pub fn cycle_field(&mut self, step: isize) {
+        let fields = self.fields();
+        let i = fields.iter().position(|f| *f == self.field).unwrap_or(0) as isize;
+        self.field = fields[(i + step).rem_euclid(fields.len() as isize) as usize];
+    }
+
+    pub fn move_bit(&mut self, rows: isize, cols: isize) {
+        let first_row = if Self::full() { 0 } else { 1 };
+        self.bit.0 = (self.bit.0 as isize + rows).clamp(first_row, 3) as usize;
+        self.bit.1 = (self.bit.1 as isize + cols).clamp(0, 2) as usize;
+    }
+
+    pub fn toggle_bit(&mut self) {
+        self.mode ^= BIT_ROWS[self.bit.0].1[self.bit.1].0;
+        self.sync_octal();
+    }
+
+    /// Takes over the octal input, once it's a valid mode
+    pub fn octal_changed(&mut self) {
+        if let Ok(mode) = u32::from_str_radix(self.octal.text(), 8) {
+            if mode <= 0o7777 {
+                self.mode = mode;
+            }
+        }
+    }
+
+    pub fn sync_octal(&mut self) {
+        self.octal.set_text(&format!("{:04o}", self.mode));
+    }
+
+    pub fn input_mut(&mut self) -> Option<&mut LineInput> {
+        match self.field {
+            PropField::Octal => Some(&mut self.octal),
+            PropField::Owner => Some(&mut self.owner),
+            PropField::Group => Some(&mut self.group),
+            _ => None,
+        }
+    }
+
+    /// Writes the changed permissions and ownership. Everything below a directory changes as
+    /// well when recursive, that's left to a job and the change to make is returned. Inside the
+    /// tree, only the bits which were toggled change.
+    pub fn apply(&mut self) -> Option<Change> {
+        let owner = self.owner.text().trim().to_string();
+        let group = self.group.text().trim().to_string();
+        let ids = (|| -> Result<_, String> {
+            let uid = if owner != self.original_owner { Some(uid_of(&owner).ok_or(format!("No user named {}", owner))?) } else { None };
+            let gid = if group != self.original_group { Some(gid_of(&group).ok_or(format!("No group named {}", group))?) } else { None };
+            Ok((uid, gid))
+        })();
+        let (uid, gid) = match ids {
+            Ok(ids) => ids,
+            Err(e) => {
+                self.result = Some((e, true));
+                return None;
+            }
+        };
+        let change = Change {
+            added: self.mode & !self.original_mode,
+            removed: self.original_mode & !self.mode,
+            uid,
+            gid,
+        };
+        if change.is_empty() {
+            self.result = Some(("Nothing was changed".to_string(), false));
+            return None;
+        }
+        if self.recursive && self.meta.is_dir() {
+            return Some(change);
+        }
+        self.result = Some(match change.apply(&self.path, |_| {}) {
+            Ok(()) => ("Changed 1 entry".to_string(), false),
+            Err(e) => (format!("{}: {}", self.path.display(), e), true),
+        });
+        // Show what's on disk now
+        if let Ok(props) = Properties::new(&self.path) {
+            let (field, bit, recursive, result) = (self.field, self.bit, self.recursive, self.result.take());
+            *self = Properties { field, bit, recursive, result, ..props };
+        }
+        None
+    }
+}
+
+/// Mode bits to set and clear and the owner and group to give, if they change
+pub struct Change {
+    added: u32,
+    removed: u32,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+impl Change {
+    fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.uid.is_none() && self.gid.is_none()
+    }
+
+    /// Changes the entry at `path`, `inside` goes through a directory's contents. Bits are added
+    /// before and removed after that, so taking away access to the directory doesn't get in the
+    /// way. The owner changes first, as that clears the setuid and setgid bits.
+    pub fn apply(&self, path: &Path, inside: impl FnOnce(&Metadata)) -> io::Result<()> {
+        let mut meta = fs::symlink_metadata(path)?;
+        // Symlinks have no permissions of their own, changing them would change the target
+        let link = meta.file_type().is_symlink();
+        let mut result = Ok(());
+        if self.uid.is_some() || self.gid.is_some() {
+            result = chown(path, self.uid, self.gid).and_then(|()| {
+                meta = fs::symlink_metadata(path)?;
+                Ok(())
+            });
+        }
+        if result.is_ok() && !link && self.added != 0 {
+            result = set_mode(path, &meta, mode(&meta) | self.added);
+        }
+        inside(&meta);
+        if result.is_ok() && !link && self.removed != 0 {
+            result = set_mode(path, &meta, (mode(&meta) | self.added) & !self.removed);
+        }
+        result
+    }
+}
+
+#[cfg(unix)]
+pub fn mode(meta: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+/// Without unix modes only the read-only attribute is there, it stands for the write bits
+#[cfg(windows)]
+pub fn mode(meta: &Metadata) -> u32 {
+    if meta.permissions().readonly() { 0o555 } else { 0o777 }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, _meta: &Metadata, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(windows)]
+fn set_mode(path: &Path, meta: &Metadata, mode: u32) -> io::Result<()> {
+    let mut permissions = meta.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    std::os::unix::fs::lchown(path, uid, gid)
+}
+
+#[cfg(windows)]
+fn chown(_path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "owners can't be changed on this platform"))
+}
+
+/// Inode, hard link count and device, as far as the platform has them
+#[cfg(unix)]
+pub fn identity(meta: &Metadata) -> Option<(u64, u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.ino(), meta.nlink(), meta.dev()))
+}
+
+#[cfg(windows)]
+pub fn identity(_meta: &Metadata) -> Option<(u64, u64, u64)> {
+    None
+}
+
+/// The owner's name, or the uid if it has none
+#[cfg(unix)]
+fn owner_name(meta: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    let uid = meta.uid();
+    lookup(|buf| {
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        // Safe, the buffer outlives the call and its size is passed along
+        let ret = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut found) };
+        (ret, if found.is_null() { std::ptr::null() } else { pwd.pw_name })
+    })
+    .unwrap_or_else(|| uid.to_string())
+}
+
+/// The group's name, or the gid if it has none
+#[cfg(unix)]
+fn group_name(meta: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    let gid = meta.gid();
+    lookup(|buf| {
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        // Safe, as above
+        let ret = unsafe { libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), buf.len(), &mut found) };
+        (ret, if found.is_null() { std::ptr::null() } else { grp.gr_name })
+    })
+    .unwrap_or_else(|| gid.to_string())
+}
+
+/// Runs a reentrant passwd or group lookup, growing the buffer while it's too small.
+/// `f` returns the error code and the name, null when nothing was found.
+#[cfg(unix)]
+fn lookup(f: impl Fn(&mut [libc::c_char]) -> (libc::c_int, *const libc::c_char)) -> Option<String> {
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let (ret, name) = f(&mut buf);
+        if ret == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if ret != 0 || name.is_null() {
+            return None;
+        }
+        // Safe, the name points into the buffer, which is still alive
+        return Some(unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy().into_owned());
+    }
+}
+
+/// A user name or a numeric uid
+#[cfg(unix)]
+fn uid_of(name: &str) -> Option<u32> {
+    if let Ok(uid) = name.parse() {
+        return Some(uid);
+    }
+    let name = std::ffi::CString::new(name).ok()?;
+    // Safe, the name is a valid C string, the result is copied out right away
+    let pwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if pwd.is_null() { None } else { Some(unsafe { (*pwd).pw_uid }) }
+}
+
+/// A group name or a numeric gid
+#[cfg(unix)]
+fn gid_of(name: &str) -> Option<u32> {
+    if let Ok(gid) = name.parse() {
+        return Some(gid);
+    }
+    let name = std::ffi::CString::new(name).ok()?;
+    // Safe, as above
+    let grp = unsafe { libc::getgrnam(name.as_ptr()) };
+    if grp.is_null() { None } else { Some(unsafe { (*grp).gr_gid }) }
+}
+
+#[cfg(windows)]
+fn owner_name(_meta: &Metadata) -> String {
+    String::new()
+}
+
+#[cfg(windows)]
+fn group_name(_meta: &Metadata) -> String {
+    String::new()
+}
+
+#[cfg(windows)]
+fn uid_of(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(windows)]
+fn gid_of(_name: &str) -> Option<u32> {
+    None
+}
@@ -0,0 +1,355 @@
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use chrono::{DateTime, Local};
+use regex::Regex;
+use crate::{app::PATH_SEAPARATOR, input::LineInput};
+
+/// Why a new name for an entry can't be used, if it can't
+pub fn invalid_name(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("The name can't be empty")
+    } else if name.contains(PATH_SEAPARATOR) {
+        Some("The name can't contain a path separator")
+    } else if name == "." || name == ".." {
+        Some("The name can't be . or ..")
+    } else {
+        None
+    }
+}
+
+/// Char position where the extension starts, or the end of the name if it has none.
+/// A leading dot, as in dotfiles, doesn't start an extension.
+pub fn stem_len(name: &str) -> usize {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name[..dot].chars().count(),
+        _ => name.chars().count(),
+    }
+}
+
+/// Editing the name of an entry in place
+pub struct Rename {
+    pub path: PathBuf,
+    pub input: LineInput,
+    pub error: Option<String>,
+}
+impl Rename {
+    /// Starts with the current name, the part before the extension selected
+    pub fn new(path: &Path) -> Self {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut input = LineInput::new();
+        input.set_text(&name);
+        input.select(0, stem_len(&name));
+        Rename { path: path.to_path_buf(), input, error: None }
+    }
+
+    /// Where the entry should go, `None` if the name wasn't changed
+    pub fn target(&self) -> Result<Option<PathBuf>, String> {
+        let name = self.input.text();
+        if let Some(reason) = invalid_name(name) {
+            return Err(reason.to_string());
+        }
+        let target = self.path.with_file_name(name);
+        // A name which isn't valid UTF-8 was only shown with replacement characters
+        if target == self.path || self.path.file_name().is_some_and(|n| n.to_string_lossy() == name) {
+            return Ok(None);
+        }
+        if target.symlink_metadata().is_ok() && !same_entry(&self.path, &target) {
+            return Err(format!("{} already exists", name));
+        }
+        Ok(Some(target))
+    }
+}
+
+/// Pairs of entries to rename, checking the new names are usable and don't collide.
+/// `names` are the new names of `sources` in the same order, unchanged ones are left out.
+pub fn plan(sources: &[PathBuf], names: &[String]) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    if names.len() != sources.len() {
+        return Err(format!("Expected {} names but got {}, lines can't be added or removed", sources.len(), names.len()));
+    }
+    let problems = check(sources, names);
+    if let Some((name, problem)) = names.iter().zip(&problems).find_map(|(n, p)| Some((n, p.as_ref()?))) {
+        return Err(format!("'{}': {}", name, problem));
+    }
+    Ok(sources.iter().zip(names)
+        .filter(|(source, name)| !unchanged(source, name))
+        .map(|(source, name)| (source.clone(), source.with_file_name(name)))
+        .collect())
+}
+
+fn unchanged(source: &Path, name: &str) -> bool {
+    source.file_name().is_some_and(|n| n.to_string_lossy() == name)
+}
+
+/// What's wrong with each of the new names, if anything
+fn check(sources: &[PathBuf], names: &[String]) -> Vec<Option<String>> {
+    let targets: Vec<Option<PathBuf>> = sources.iter().zip(names)
+        .map(|(source, name)| if unchanged(source, name) { None } else { Some(source.with_file_name(name)) })
+        .collect();
+    sources.iter().zip(names).zip(&targets).enumerate()
+        .map(|(i, ((source, name), target))| {
+            let target = target.as_ref()?;
+            if let Some(reason) = invalid_name(name) {
+                return Some(reason.to_string());
+            }
+            let other = sources.iter().zip(&targets).enumerate()
+                .find(|(j, (_, t))| *j != i && t.as_ref() == Some(target));
+            if let Some((_, (other, _))) = other {
+                return Some(format!("{} would be renamed to it too", file_name(other)));
+            }
+            // Taken by an entry which isn't renamed away
+            let freed = sources.iter().zip(&targets).any(|(s, t)| s == target && t.is_some());
+            if !freed && target.symlink_metadata().is_ok() && !same_entry(source, target) {
+                return Some("already exists".to_string());
+            }
+            None
+        })
+        .collect()
+}
+
+/// Whether the new name only changes the case and finds the entry itself, as on case insensitive filesystems
+fn same_entry(source: &Path, target: &Path) -> bool {
+    let lower = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_lowercase());
+    lower(source) == lower(target) && same_file(source, target)
+}
+
+/// Whether both paths lead to the very same entry, not following symlinks
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.symlink_metadata(), b.symlink_metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Whether both paths lead to the very same entry, not following symlinks
+#[cfg(windows)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    // The file index isn't exposed on stable, the final path names the entry with its real case
+    let is_link = |p: &Path| p.symlink_metadata().map_or(true, |m| m.file_type().is_symlink());
+    if is_link(a) || is_link(b) {
+        return false;
+    }
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Renames all the pairs, going through temporary names when some take over the names of others,
+/// as in swaps and cycles
+pub fn rename_all(pairs: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+    let overlapping = pairs.iter().any(|(_, to)| pairs.iter().any(|(from, _)| from == to));
+    if !overlapping {
+        for (from, to) in pairs {
+            fs::rename(from, to).map_err(|e| format!("{}: {}", from.display(), e))?;
+        }
+        return Ok(());
+    }
+    let mut temporary = vec![];
+    for (i, (from, _)) in pairs.iter().enumerate() {
+        let tmp = from.with_file_name(format!(".pc-rename-{}-{}", std::process::id(), i));
+        if let Err(e) = fs::rename(from, &tmp) {
+            // Put back what was already moved aside
+            for (from, tmp) in temporary.iter().rev() {
+                fs::rename(tmp, from).ok();
+            }
+            return Err(format!("{}: {}", from.display(), e));
+        }
+        temporary.push((from.clone(), tmp));
+    }
+    for ((_, tmp), (from, to)) in temporary.iter().zip(pairs) {
+        fs::rename(tmp, to).map_err(|e| format!("{}: {}, it was left as {}", from.display(), e, tmp.display()))?;
+    }
+    Ok(())
+}
+
+/// The renames about to be made, shown for confirmation
+pub struct RenamePreview {
+    pub pairs: Vec<(PathBuf, PathBuf)>,
+    pub scroll: usize,
+}
+impl RenamePreview {
+    pub fn new(pairs: Vec<(PathBuf, PathBuf)>) -> Self {
+        RenamePreview { pairs, scroll: 0 }
+    }
+
+    pub fn scroll_by(&mut self, step: isize) {
+        let max = self.pairs.len().saturating_sub(1) as isize;
+        self.scroll = (self.scroll as isize + step).clamp(0, max) as usize;
+    }
+}
+
+/// Fills in the tokens of a name template: `{n}` counts the entries from 1, `{n:3}` pads the count
+/// to 3 digits, `{name}` and `{ext}` are the parts of the old name around the last dot and
+/// `{mtime}` the modification date, formatted like `{mtime:%Y-%m-%d %H.%M}`.
+/// `{{` and `}}` stand for braces, `${..}` is left alone for regex groups.
+/// Token values are passed through `escape`.
+fn expand(template: &str, n: usize, name: &str, mtime: Option<SystemTime>, escape: fn(&str) -> String) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => { chars.next(); out.push('{') }
+            '}' if chars.peek() == Some(&'}') => { chars.next(); out.push('}') }
+            '{' if !out.ends_with('$') => {
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => token.push(c),
+                        None => return Err("A { isn't closed, {{ stands for a brace".to_string()),
+                    }
+                }
+                let (key, arg) = match token.split_once(':') {
+                    Some((key, arg)) => (key, Some(arg)),
+                    None => (token.as_str(), None),
+                };
+                let stem: String = name.chars().take(stem_len(name)).collect();
+                let value = match (key, arg) {
+                    ("n", None) => n.to_string(),
+                    ("n", Some(width)) => {
+                        let width: usize = width.parse().map_err(|_| format!("{{n:{}}} needs a number of digits", width))?;
+                        format!("{:0width$}", n, width = width)
+                    }
+                    ("name", None) => stem.clone(),
+                    ("ext", None) => name[stem.len()..].trim_start_matches('.').to_string(),
+                    ("mtime", format) => {
+                        let mtime: DateTime<Local> = mtime.ok_or("The modification time isn't available")?.into();
+                        let mut s = String::new();
+                        write!(s, "{}", mtime.format(format.unwrap_or("%Y-%m-%d")))
+                            .map_err(|_| format!("Invalid date format {}", format.unwrap_or_default()))?;
+                        s
+                    }
+                    _ => return Err(format!("Unknown token {{{}}}", token)),
+                };
+                out.push_str(&escape(&value));
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchField {
+    Find,
+    Replace,
+}
+
+/// An entry of the batch rename preview
+pub struct BatchRow {
+    pub path: PathBuf,
+    pub name: String,
+    pub new_name: String,
+    /// Why the new name can't be used
+    pub problem: Option<String>,
+}
+impl BatchRow {
+    pub fn changed(&self) -> bool {
+        self.name != self.new_name
+    }
+}
+
+/// Renaming several entries at once, replacing what a regex matches in their names, or the
+/// whole names when there's no regex, by a template
+pub struct BatchRename {
+    sources: Vec<PathBuf>,
+    mtimes: Vec<Option<SystemTime>>,
+    pub find: LineInput,
+    pub replace: LineInput,
+    pub field: BatchField,
+    pub rows: Vec<BatchRow>,
+    /// Problem with the regex or the template, nothing is renamed then
+    pub error: Option<String>,
+    pub scroll: usize,
+}
+impl BatchRename {
+    pub fn new(sources: Vec<PathBuf>) -> Self {
+        let mtimes = sources.iter()
+            .map(|p| p.symlink_metadata().and_then(|m| m.modified()).ok())
+            .collect();
+        let mut batch = BatchRename {
+            sources,
+            mtimes,
+            find: LineInput::new(),
+            replace: LineInput::new(),
+            field: BatchField::Find,
+            rows: vec![],
+            error: None,
+            scroll: 0,
+        };
+        batch.update();
+        batch
+    }
+
+    pub fn switch_field(&mut self) {
+        self.field = match self.field {
+            BatchField::Find => BatchField::Replace,
+            BatchField::Replace => BatchField::Find,
+        };
+    }
+
+    pub fn input_mut(&mut self) -> &mut LineInput {
+        match self.field {
+            BatchField::Find => &mut self.find,
+            BatchField::Replace => &mut self.replace,
+        }
+    }
+
+    /// Works out the new names after the find or replace text changed
+    pub fn update(&mut self) {
+        let names: Vec<String> = self.sources.iter().map(|p| file_name(p)).collect();
+        let new_names = self.new_names(&names);
+        self.error = new_names.as_ref().err().cloned();
+        let new_names = new_names.unwrap_or_else(|_| names.clone());
+        let problems = check(&self.sources, &new_names);
+        self.rows = self.sources.iter().zip(names).zip(new_names).zip(problems)
+            .map(|(((path, name), new_name), problem)| BatchRow { path: path.clone(), name, new_name, problem })
+            .collect();
+    }
+
+    fn new_names(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let template = self.replace.text();
+        if self.find.text().is_empty() {
+            if template.is_empty() {
+                return Ok(names.to_vec());
+            }
+            return names.iter().zip(&self.mtimes).enumerate()
+                .map(|(i, (name, mtime))| expand(template, i + 1, name, *mtime, str::to_string))
+                .collect();
+        }
+        let regex = Regex::new(self.find.text()).map_err(|e| match e {
+            regex::Error::Syntax(_) => "Invalid regex".to_string(),
+            e => e.to_string(),
+        })?;
+        names.iter().zip(&self.mtimes).enumerate()
+            .map(|(i, (name, mtime))| {
+                // Token values are taken literally, not as group references
+                let replacement = expand(template, i + 1, name, *mtime, |v| v.replace('$', "$$"))?;
+                Ok(regex.replace_all(name, replacement.as_str()).into_owned())
+            })
+            .collect()
+    }
+
+    /// Entries to rename, if all the new names can be used
+    pub fn pairs(&self) -> Option<Vec<(PathBuf, PathBuf)>> {
+        if self.error.is_some() || self.rows.iter().any(|r| r.problem.is_some()) {
+            return None;
+        }
+        Some(self.rows.iter()
+            .filter(|r| r.changed())
+            .map(|r| (r.path.clone(), r.path.with_file_name(&r.new_name)))
+            .collect())
+    }
+
+    pub fn scroll_by(&mut self, step: isize) {
+        let max = self.rows.len().saturating_sub(1) as isize;
+        self.scroll = (self.scroll as isize + step).clamp(0, max) as usize;
+    }
+}
@@ -0,0 +1,54 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use crate::entry::Entry;
+
+/// Entries picked to act on together, possibly from several directories.
+///
+/// Sizes are captured when an entry is picked, a directory counts with its own size.
+#[derive(Default)]
+pub struct Selection {
+    paths: BTreeMap<PathBuf, u64>,
+}
+impl Selection {
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains_key(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.paths.values().sum()
+    }
+
+    pub fn insert(&mut self, entry: &Entry) {
+        self.paths.insert(entry.path.clone(), entry.size);
+    }
+
+    /// Selects an entry if it isn't yet, deselects it otherwise
+    pub fn toggle(&mut self, entry: &Entry) {
+        if self.paths.remove(&entry.path).is_none() {
+            self.insert(entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.paths.keys().cloned().collect()
+    }
+
+    /// Forgets entries which were moved or deleted since they were picked
+    pub fn prune(&mut self) {
+        self.paths.retain(|p, _| p.symlink_metadata().is_ok());
+    }
+}
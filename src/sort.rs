@@ -0,0 +1,146 @@
+use std::{cmp::Ordering, str::FromStr};
+use crate::entry::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    /// Name, comparing runs of digits by their numeric value (`file2` < `file10`)
+    Natural,
+    Extension,
+    Size,
+    Modified,
+    Changed,
+}
+impl SortKey {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Natural => "natural",
+            SortKey::Extension => "ext",
+            SortKey::Size => "size",
+            SortKey::Modified => "mtime",
+            SortKey::Changed => "ctime",
+        }
+    }
+}
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "natural" | "version" => Ok(SortKey::Natural),
+            "ext" | "extension" => Ok(SortKey::Extension),
+            "size" => Ok(SortKey::Size),
+            "mtime" => Ok(SortKey::Modified),
+            "ctime" => Ok(SortKey::Changed),
+            _ => Err(format!("unknown sort key '{}', expected one of name, natural, ext, size, mtime, ctime", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+    pub dirs_first: bool,
+    pub case_sensitive: bool,
+}
+impl Default for SortOrder {
+    fn default() -> Self {
+        SortOrder {
+            key: SortKey::Name,
+            reverse: false,
+            dirs_first: true,
+            case_sensitive: false,
+        }
+    }
+}
+impl SortOrder {
+    pub fn sort(&self, entries: &mut [Entry]) {
+        entries.sort_by(|a, b| self.compare(a, b));
+    }
+
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        if self.dirs_first && a.is_dir != b.is_dir {
+            return b.is_dir.cmp(&a.is_dir);
+        }
+        let ord = match self.key {
+            SortKey::Name => self.compare_names(a, b),
+            SortKey::Natural => natural_cmp(&self.fold(a), &self.fold(b)),
+            SortKey::Extension => extension(a).cmp(&extension(b))
+                .then_with(|| self.compare_names(a, b)),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+            SortKey::Changed => a.changed.cmp(&b.changed),
+        }
+        // Keep the order stable between listings when the keys are equal
+        .then_with(|| a.name.cmp(&b.name));
+        if self.reverse { ord.reverse() } else { ord }
+    }
+
+    fn compare_names(&self, a: &Entry, b: &Entry) -> Ordering {
+        self.fold(a).cmp(&self.fold(b))
+    }
+
+    fn fold(&self, entry: &Entry) -> String {
+        if self.case_sensitive {
+            entry.name_lossy()
+        } else {
+            entry.name_lossy().to_lowercase()
+        }
+    }
+
+    /// Short summary for the ui, e.g. `size desc`
+    pub fn describe(&self) -> String {
+        let mut s = self.key.label().to_string();
+        if self.reverse { s.push_str(" desc") }
+        if !self.dirs_first { s.push_str(", mixed") }
+        if self.case_sensitive { s.push_str(", case") }
+        s
+    }
+}
+
+fn extension(entry: &Entry) -> String {
+    entry.path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Compares strings so that embedded numbers are ordered by value
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let x_trimmed = x.trim_start_matches('0');
+                let y_trimmed = y.trim_start_matches('0');
+                let ord = x_trimmed.len().cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ord != Ordering::Equal { return ord }
+            }
+            (Some(x), Some(y)) => {
+                if x != y { return x.cmp(&y) }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut s = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if !c.is_ascii_digit() { break }
+        s.push(c);
+        chars.next();
+    }
+    s
+}
@@ -0,0 +1,95 @@
+use std::{
+    env,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const APP_DIR: &str = "partial-commander";
+
+fn env_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env_dir("HOME").or_else(|| env_dir("USERPROFILE"))
+}
+
+/// The user's data directory shared by all applications, `$XDG_DATA_HOME` or `~/.local/share`
+#[cfg(not(windows))]
+pub fn data_home() -> Option<PathBuf> {
+    env_dir("XDG_DATA_HOME")
+        .or_else(|| home_dir().map(|h| h.join(".local").join("share")))
+}
+
+#[cfg(windows)]
+pub fn data_home() -> Option<PathBuf> {
+    env_dir("LOCALAPPDATA").or_else(|| env_dir("APPDATA"))
+}
+
+/// Where persistent state like the jump database lives,
+/// `$XDG_DATA_HOME/partial-commander` or `~/.local/share/partial-commander`
+pub fn data_dir() -> Option<PathBuf> {
+    data_home().map(|d| d.join(APP_DIR))
+}
+
+/// Where user edited files like bookmarks live,
+/// `$XDG_CONFIG_HOME/partial-commander` or `~/.config/partial-commander`
+#[cfg(not(windows))]
+pub fn config_dir() -> Option<PathBuf> {
+    env_dir("XDG_CONFIG_HOME")
+        .or_else(|| home_dir().map(|h| h.join(".config")))
+        .map(|d| d.join(APP_DIR))
+}
+
+#[cfg(windows)]
+pub fn config_dir() -> Option<PathBuf> {
+    env_dir("APPDATA").map(|d| d.join(APP_DIR))
+}
+
+/// Writes the file through a temporary sibling, so a crash never leaves it half written
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Named after the process, so sessions saving at once don't write into the same file
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// Writes a new file in the temporary directory, readable only by the user. It's never one
+/// which was there already, as someone else could have put it there to read or swap it.
+pub fn write_temp(prefix: &str, contents: &str) -> io::Result<PathBuf> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    for n in 0..100 {
+        let path = env::temp_dir().join(format!("{}-{}-{}-{}.txt", prefix, std::process::id(), nanos, n));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = match options.open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        if let Err(e) = file.write_all(contents.as_bytes()) {
+            fs::remove_file(&path).ok();
+            return Err(e);
+        }
+        return Ok(path);
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "no free temporary file name"))
+}
+
+/// Seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,514 @@
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use chrono::{Local, NaiveDateTime};
+use tui::widgets::ListState;
+use crate::storage;
+
+const INFO_EXT: &str = ".trashinfo";
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// A trash directory as described by the freedesktop.org trash specification,
+/// `files` holds the trashed entries and `info` a `.trashinfo` file for each of them
+pub struct Trash {
+    pub root: PathBuf,
+    /// Top directory of the filesystem original paths are relative to, `None` for the home trash
+    pub top: Option<PathBuf>,
+}
+impl Trash {
+    /// `$XDG_DATA_HOME/Trash`
+    pub fn home() -> Option<Trash> {
+        storage::data_home().map(|d| Trash { root: d.join("Trash"), top: None })
+    }
+
+    fn files(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.root.join("info")
+    }
+
+    pub fn label(&self) -> String {
+        match &self.top {
+            None => "Home trash".to_string(),
+            Some(top) => format!("Trash on {}", top.display()),
+        }
+    }
+
+    /// Trashed entries, most recently deleted first
+    pub fn items(&self) -> Vec<TrashItem> {
+        let mut items = vec![];
+        let dir = match self.info().read_dir() {
+            Ok(dir) => dir,
+            Err(_) => return items,
+        };
+        for info in dir.flatten() {
+            let info_name = info.file_name().to_string_lossy().into_owned();
+            let name = match info_name.strip_suffix(INFO_EXT) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let contents = match fs::read_to_string(info.path()) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            let mut original = None;
+            let mut deleted = None;
+            for line in contents.lines() {
+                if let Some(path) = line.strip_prefix("Path=") {
+                    original = Some(decode(path));
+                } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+                    deleted = NaiveDateTime::parse_from_str(date, DATE_FORMAT).ok();
+                }
+            }
+            let original = match (original, &self.top) {
+                (Some(path), Some(top)) if path.is_relative() => top.join(path),
+                (Some(path), _) => path,
+                (None, _) => continue,
+            };
+            let path = self.files().join(&name);
+            let meta = match fs::symlink_metadata(&path) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            items.push(TrashItem {
+                name: name.into(),
+                original,
+                deleted,
+                is_dir: meta.is_dir(),
+                size: meta.len(),
+                path,
+                info_path: info.path(),
+            });
+        }
+        items.sort_by_key(|item| std::cmp::Reverse(item.deleted));
+        items
+    }
+}
+
+pub struct TrashItem {
+    /// Name within the trash, not necessarily the original one
+    pub name: OsString,
+    pub original: PathBuf,
+    pub deleted: Option<NaiveDateTime>,
+    pub is_dir: bool,
+    /// Size of the entry itself, not of a directory's contents
+    pub size: u64,
+    /// Where the entry is kept in the trash
+    pub path: PathBuf,
+    info_path: PathBuf,
+}
+impl TrashItem {
+    /// Moves the entry back to `target`, which must not exist
+    pub fn restore(&self, target: &Path) -> io::Result<()> {
+        restore(&self.path, &self.info_path, target)
+    }
+
+    /// Deletes the entry for good
+    pub fn purge(&self) -> io::Result<()> {
+        if self.is_dir {
+            fs::remove_dir_all(&self.path)?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        fs::remove_file(&self.info_path)
+    }
+}
+
+/// Every trash directory of the user which exists, the home trash first
+pub fn all() -> Vec<Trash> {
+    let mut trashes: Vec<Trash> = Trash::home().into_iter().collect();
+    for top in mount_points() {
+        for root in top_trash_roots(&top) {
+            if root.join("info").is_dir() && !trashes.iter().any(|t| t.root == root) {
+                trashes.push(Trash { root, top: Some(top.clone()) });
+            }
+        }
+    }
+    trashes
+}
+
+fn restore(path: &Path, info_path: &Path, target: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(target).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "already exists"));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(path, target)?;
+    fs::remove_file(info_path).ok();
+    Ok(())
+}
+
+/// Moves an entry out of a trash's `files` directory to `target`, dropping its `.trashinfo`
+pub fn untrash(path: &Path, target: &Path) -> io::Result<()> {
+    let mut info_name = path.file_name().unwrap_or_default().to_os_string();
+    info_name.push(INFO_EXT);
+    let info_path = path.parent()
+        .and_then(|files| files.parent())
+        .map(|root| root.join("info").join(info_name))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not in a trash directory"))?;
+    restore(path, &info_path, target)
+}
+
+/// Moves an entry into the trash of its filesystem, returning where it was put
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    let trash = trash_for(path)?;
+    fs::create_dir_all(trash.files())?;
+    fs::create_dir_all(trash.info())?;
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "can't trash a root directory"))?;
+    let original = match &trash.top {
+        Some(top) => path.strip_prefix(top).unwrap_or(path),
+        None => path,
+    };
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode(original),
+        Local::now().format(DATE_FORMAT),
+    );
+
+    // Claiming the info file first makes the name ours, even with others trashing at the same time
+    let mut n = 1;
+    loop {
+        let candidate = numbered(Path::new(name), n);
+        let candidate = candidate.as_os_str();
+        let mut info_name = candidate.to_os_string();
+        info_name.push(INFO_EXT);
+        let info_path = trash.info().join(info_name);
+        let target = trash.files().join(candidate);
+        n += 1;
+        if fs::symlink_metadata(&target).is_ok() {
+            continue;
+        }
+        let mut info = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(info) => info,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let result = info.write_all(contents.as_bytes())
+            .and_then(|_| fs::rename(path, &target))
+            .map(|_| target);
+        if result.is_err() {
+            fs::remove_file(&info_path).ok();
+        }
+        return result;
+    }
+}
+
+/// `name (n).ext`, or the name itself for the first one
+pub fn numbered(path: &Path, n: usize) -> PathBuf {
+    if n <= 1 {
+        return path.to_path_buf();
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let numbered = match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{} ({}){}", &name[..dot], n, &name[dot..]),
+        _ => format!("{} ({})", name, n),
+    };
+    path.with_file_name(numbered)
+}
+
+/// The first `name (n).ext` next to `path` which doesn't exist yet
+pub fn free_name(path: &Path) -> PathBuf {
+    (2..).map(|n| numbered(path, n))
+        .find(|p| fs::symlink_metadata(p).is_err())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Percent encodes everything but unreserved characters and separators, as `.trashinfo` paths are
+pub fn encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &b in path_bytes(path).iter() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+pub fn decode(encoded: &str) -> PathBuf {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    path_from_bytes(decoded)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(windows)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(windows)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The home trash if `path` is on the same filesystem, otherwise one at the top of its filesystem
+#[cfg(unix)]
+fn trash_for(path: &Path) -> io::Result<Trash> {
+    use std::os::unix::fs::MetadataExt;
+    let dev = fs::symlink_metadata(path)?.dev();
+    if let Some(home) = Trash::home() {
+        // The home trash may not exist yet, what matters is the filesystem it would be on
+        let home_dev = home.root.ancestors().find_map(|p| p.metadata().ok()).map(|m| m.dev());
+        if home_dev == Some(dev) {
+            return Ok(home);
+        }
+    }
+    let mut top = path.parent().unwrap_or(path);
+    while let Some(parent) = top.parent() {
+        if parent.metadata()?.dev() != dev { break }
+        top = parent;
+    }
+    let roots = top_trash_roots(top);
+    // `$top/.Trash/$uid` is only trusted if an administrator set `.Trash` up as a sticky directory
+    let shared = top.join(".Trash");
+    let shared_ok = fs::symlink_metadata(&shared)
+        .map(|m| m.is_dir() && m.mode() & 0o1000 != 0)
+        .unwrap_or(false);
+    let root = if shared_ok {
+        roots[0].clone()
+    } else {
+        roots[1].clone()
+    };
+    if !root.is_dir() {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(&root)
+            .map_err(|e| io::Error::new(e.kind(), format!("no trash available on this filesystem: {}", e)))?;
+    }
+    Ok(Trash { root, top: Some(top.to_path_buf()) })
+}
+
+#[cfg(windows)]
+fn trash_for(_path: &Path) -> io::Result<Trash> {
+    Err(io::Error::new(io::ErrorKind::Other, "the trash is not supported on this platform"))
+}
+
+/// `$top/.Trash/$uid` and `$top/.Trash-$uid`
+#[cfg(unix)]
+fn top_trash_roots(top: &Path) -> [PathBuf; 2] {
+    // Safe, getuid can't fail
+    let uid = unsafe { libc::getuid() };
+    [top.join(".Trash").join(uid.to_string()), top.join(format!(".Trash-{}", uid))]
+}
+
+#[cfg(windows)]
+fn top_trash_roots(_top: &Path) -> [PathBuf; 2] {
+    [PathBuf::new(), PathBuf::new()]
+}
+
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    mounts.lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        // Spaces and such are escaped as octal in the mount table
+        .map(|p| p.replace("\\040", " ").replace("\\011", "\t").replace("\\134", "\\"))
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+    vec![]
+}
+
+/// What is being asked in the trash view
+pub enum Question {
+    /// Restoring would overwrite an existing entry, restore under this name instead?
+    RestoreAs(PathBuf),
+    Purge,
+}
+
+/// Browsing the trash directories, with the trashed entries of one of them listed
+pub struct TrashView {
+    trashes: Vec<Trash>,
+    pub trash_state: ListState,
+    items: Vec<TrashItem>,
+    pub state: ListState,
+    /// Names inside the selected entry when it's a directory, read as it gets selected
+    children: Vec<String>,
+    pub question: Option<Question>,
+    pub error: Option<String>,
+}
+impl TrashView {
+    pub fn new() -> Self {
+        let mut view = TrashView {
+            trashes: all(),
+            trash_state: ListState::default(),
+            items: vec![],
+            state: ListState::default(),
+            children: vec![],
+            question: None,
+            error: None,
+        };
+        if !view.trashes.is_empty() {
+            view.trash_state.select(Some(0));
+        }
+        view.reload();
+        view
+    }
+
+    pub fn trashes(&self) -> &[Trash] {
+        &self.trashes
+    }
+
+    pub fn trash(&self) -> Option<&Trash> {
+        self.trash_state.selected().and_then(|i| self.trashes.get(i))
+    }
+
+    pub fn items(&self) -> &[TrashItem] {
+        &self.items
+    }
+
+    pub fn selected(&self) -> Option<&TrashItem> {
+        self.state.selected().and_then(|i| self.items.get(i))
+    }
+
+    pub fn children(&self) -> &[String] {
+        &self.children
+    }
+
+    fn select(&mut self, idx: Option<usize>) {
+        self.state.select(idx);
+        self.children = match self.selected() {
+            Some(item) if item.is_dir => item.path.read_dir()
+                .map(|dir| dir.flatten().map(|e| e.file_name().to_string_lossy().into_owned()).collect())
+                .unwrap_or_default(),
+            _ => vec![],
+        };
+    }
+
+    /// Re-reads the shown trash, keeping the selection in place where possible
+    pub fn reload(&mut self) {
+        self.items = self.trash().map(|t| t.items()).unwrap_or_default();
+        let idx = self.state.selected().unwrap_or(0).min(self.items.len().saturating_sub(1));
+        self.select(if self.items.is_empty() { None } else { Some(idx) });
+    }
+
+    pub fn next(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.select(Some((i + 1).min(self.items.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.select(Some(i.saturating_sub(1)));
+        }
+    }
+
+    /// Switches to another trash directory
+    pub fn cycle_trash(&mut self, step: isize) {
+        if self.trashes.is_empty() { return }
+        let len = self.trashes.len() as isize;
+        let i = self.trash_state.selected().unwrap_or(0) as isize;
+        self.trash_state.select(Some((i + step).rem_euclid(len) as usize));
+        self.state.select(Some(0));
+        self.reload();
+    }
+
+    /// Restores the selected entry to where it was, or asks for another name if that's taken
+    pub fn restore(&mut self) {
+        let (result, original) = match self.selected() {
+            Some(item) if fs::symlink_metadata(&item.original).is_ok() => {
+                self.question = Some(Question::RestoreAs(free_name(&item.original)));
+                return;
+            }
+            Some(item) => (item.restore(&item.original), item.original.clone()),
+            None => return,
+        };
+        self.finish(result, &original);
+    }
+
+    pub fn ask_purge(&mut self) {
+        if self.selected().is_some() {
+            self.question = Some(Question::Purge);
+        }
+    }
+
+    /// Answers yes to the pending question
+    pub fn confirm(&mut self) {
+        let (result, path) = match (self.question.take(), self.selected()) {
+            (Some(Question::RestoreAs(target)), Some(item)) => (item.restore(&target), target),
+            (Some(Question::Purge), Some(item)) => (item.purge(), item.path.clone()),
+            _ => return,
+        };
+        self.finish(result, &path);
+    }
+
+    fn finish(&mut self, result: io::Result<()>, path: &Path) {
+        if let Err(e) = result {
+            self.error = Some(format!("{}: {}", path.display(), e));
+        }
+        self.reload();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_round_trips() {
+        for path in ["/tmp/plain-name_1.txt", "/tmp/über/naïve", "/tmp/100% done", "/tmp/tab\there", "/tmp/new\nline", "/tmp/%41"] {
+            let encoded = encode(Path::new(path));
+            assert!(!encoded.contains(['\t', '\n', ' ']), "{:?}", encoded);
+            assert_eq!(decode(&encoded), Path::new(path));
+        }
+    }
+
+    #[test]
+    fn encodes_like_trashinfo() {
+        assert_eq!(encode(Path::new("/home/me/a b%.txt")), "/home/me/a%20b%25.txt");
+        assert_eq!(encode(Path::new("/é")), "/%C3%A9");
+    }
+
+    #[test]
+    fn decodes_stray_percents_as_is() {
+        assert_eq!(decode("/a%2"), Path::new("/a%2"));
+        assert_eq!(decode("/a%zz"), Path::new("/a%zz"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trips_names_that_are_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let path = Path::new(OsStr::from_bytes(b"/tmp/bad\xff\xfename"));
+        assert_eq!(decode(&encode(path)), path);
+    }
+}
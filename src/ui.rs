@@ -1,35 +1,75 @@
-use std::error::Error;
+use std::{error::Error, path::{Path, PathBuf}};
 use tui::{
     backend::Backend,
     Frame,
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, Wrap},
     layout::{Layout, Constraint, Direction, Margin, Alignment, Rect, Corner},
     text::{Span, Spans},
     style::{Color, Style, Modifier},
 };
-use crate::app::App;
+use chrono::{DateTime, Local};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use crate::{app::{App, PATH_SEAPARATOR}, bookmarks::{BookmarkPopup, Bookmarks, EditField}, create::{CreateKind, CreatePrompt}, link::LinkPrompt, entry::{self, Entry}, filter::Filter, finder::Finder, goto::GotoPrompt, hexdump, history::HistoryPopup, input::LineInput, jobs::{Conflict, JobStatus, Side}, jump::JumpPrompt, mode::{ConflictDialog, Mode}, pager::{self, Pager, PagerPrompt}, preview::Previewer, props::{self, PropField, Properties, BIT_ROWS}, rename::{BatchField, BatchRename, Rename, RenamePreview}, selection::Selection, sort::SortOrder, trash::{Question, TrashView}};
 
 pub struct UiData {
     parent_title: String,
     current_title: String,
-    parent_list: Vec<String>,
-    current_list: Vec<String>,
-    current_last_selected: usize,
-    child_list: Result<Vec<String>, Box<dyn Error>>,
-    child_content: Option<Vec<String>>,
+    current_last_selected: Option<PathBuf>,
+    child_list: Result<Vec<Entry>, Box<dyn Error>>,
+    /// Preview of the selected file, highlighted where its type is known
+    child_content: Option<Vec<Spans<'static>>>,
+    /// The preview is still being read, nothing is shown meanwhile
+    child_loading: bool,
+    /// What the previewed binary file is
+    child_type: Option<&'static str>,
     child_is_folder: bool,
+    previewer: Previewer,
+    /// Lines of the previewed file scrolled past
+    preview_scroll: usize,
+    /// The scroll and the area of the last preview asked for
+    preview_requested: Option<(usize, Rect)>,
+    /// Feedback for the last command, shown on the bottom line until the next key
+    message: Option<(String, bool)>,
 }
 impl UiData {
-    pub fn new() -> Self {
+    pub fn set_message(&mut self, msg: String) {
+        self.message = Some((msg, false));
+    }
+
+    pub fn set_error(&mut self, msg: String) {
+        self.message = Some((msg, true));
+    }
+
+    pub fn clear_message(&mut self) {
+        self.message = None;
+    }
+
+    /// Scrolls the preview of a file by `delta` lines, it's clamped once read
+    pub fn scroll_preview(&mut self, delta: isize) {
+        if !self.child_is_folder {
+            self.preview_scroll = self.preview_scroll.saturating_add_signed(delta);
+        }
+    }
+
+    /// Lines the file preview shows at once
+    pub fn preview_page(&self) -> isize {
+        self.preview_requested.map_or(1, |(_, rect)| rect.height.max(1) as isize)
+    }
+
+    pub fn new(previewer: Previewer) -> Self {
         UiData {
             parent_title: String::from(""),
             current_title: String::from(""),
-            parent_list: vec!(),
-            current_list: vec!(),
-            current_last_selected: 0,
+            current_last_selected: None,
             child_list: Ok(vec!()),
             child_content: None,
+            child_loading: false,
+            child_type: None,
             child_is_folder: true,
+            previewer,
+            preview_scroll: 0,
+            preview_requested: None,
+            message: None,
         }
     }
 }
@@ -39,9 +79,13 @@ fn draw_empty_dir<B: Backend>(f: &mut Frame<B>, app: &mut App, rect: Rect, ) {
     if app.is_dirs_only() {
         explanation = "Note that there may be files, but are hidden because of directory mode";
     }
+    draw_notice(f, "Empty directory", explanation, rect);
+}
+
+fn draw_notice<B: Backend>(f: &mut Frame<B>, title: &str, explanation: &str, rect: Rect) {
     let s = vec![
         Spans::from(vec![
-            Span::styled("Empty directory", Style::default().add_modifier(Modifier::ITALIC)),
+            Span::styled(title, Style::default().add_modifier(Modifier::ITALIC)),
         ]),
         Spans::from(vec![
             Span::styled(explanation, Style::default().fg(Color::DarkGray)),
@@ -53,33 +97,963 @@ fn draw_empty_dir<B: Backend>(f: &mut Frame<B>, app: &mut App, rect: Rect, ) {
     f.render_widget(paragraph, rect);
 }
 
-pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App, redraw_only: bool, ui_data: &mut UiData, current_directory_state: &mut ListState, parent_directory_state: &mut ListState) {
+/// Name of an entry with the chars at the given positions highlighted.
+/// Symlinks show where they point to, broken ones in red.
+fn highlighted_name(entry: &Entry, positions: &[usize]) -> Spans<'static> {
+    let style = match (&entry.link_target, entry.broken) {
+        (Some(_), true) => Style::default().fg(Color::Red),
+        (Some(_), false) => Style::default().fg(Color::Cyan),
+        (None, _) => Style::default(),
+    };
+    let mut name = highlighted_text(&entry.display_name(), positions, style);
+    if let Some(target) = &entry.link_target {
+        let style = if entry.broken { style } else { Style::default().fg(Color::DarkGray) };
+        name.0.push(Span::styled(format!(" -> {}", target.display()), style));
+    }
+    name
+}
+
+fn highlighted_text(text: &str, positions: &[usize], style: Style) -> Spans<'static> {
+    if positions.is_empty() {
+        return Spans::from(Span::styled(text.to_string(), style));
+    }
+    let highlight = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let spans: Vec<Span> = text
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if positions.contains(&i) {
+                Span::styled(c.to_string(), highlight)
+            } else {
+                Span::styled(c.to_string(), style)
+            }
+        })
+        .collect();
+    Spans::from(spans)
+}
+
+/// Draws a text input on a single line, placing the terminal cursor in it
+fn draw_input<B: Backend>(f: &mut Frame<B>, prefix: Vec<Span>, input: &LineInput, suffix: Vec<Span>, rect: Rect) {
+    let prefix_width: usize = prefix.iter().map(|s| s.width()).sum();
+    let before_cursor: String = input.text().chars().take(input.cursor()).collect();
+    let x = rect.x as usize + prefix_width + before_cursor.width();
+    let mut spans = prefix;
+    match input.selection() {
+        Some((from, to)) => {
+            let chars: Vec<char> = input.text().chars().collect();
+            spans.push(Span::raw(chars[..from].iter().collect::<String>()));
+            spans.push(Span::styled(chars[from..to].iter().collect::<String>(), Style::default().add_modifier(Modifier::REVERSED)));
+            spans.push(Span::raw(chars[to..].iter().collect::<String>()));
+        }
+        None => spans.push(Span::raw(input.text().to_string())),
+    }
+    spans.extend(suffix);
+    f.render_widget(Paragraph::new(Spans::from(spans)), rect);
+    f.set_cursor((x as u16).min(rect.right().saturating_sub(1)), rect.y);
+}
+
+fn draw_filter_input<B: Backend>(f: &mut Frame<B>, filter: &Filter, rect: Rect) {
+    draw_input(
+        f,
+        vec![Span::styled("/", Style::default().fg(Color::Yellow))],
+        &filter.input,
+        vec![Span::styled(format!("  [{}, Tab to switch]", filter.mode.label()), Style::default().fg(Color::DarkGray))],
+        rect,
+    );
+}
+
+/// A rect of the given percentage of `r`, centered in it
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ].as_ref())
+        .split(r);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ].as_ref())
+        .split(rows[1])[1]
+}
+
+fn draw_bookmarks<B: Backend>(f: &mut Frame<B>, bookmarks: &Bookmarks, popup: &mut BookmarkPopup, rect: Rect) {
+    let rect = centered_rect(70, 60, rect);
+    f.render_widget(Clear, rect);
+    let mut title = vec![Span::styled("Bookmarks", Style::default().fg(Color::Green))];
+    match &popup.error {
+        Some(error) => title.push(Span::styled(format!(" {}", error), Style::default().fg(Color::Red))),
+        None => title.push(Span::styled(
+            " Enter: go, r: rename, e: edit path, d: delete",
+            Style::default().fg(Color::DarkGray),
+        )),
+    }
+    let block = Block::default()
+        .title(Spans::from(title))
+        .borders(Borders::ALL);
+    let inner = block.inner(rect);
+    f.render_widget(block, rect);
+    if bookmarks.is_empty() {
+        draw_notice(f, "No bookmarks yet", "Press m and a letter to bookmark the current directory", inner);
+        return;
+    }
+
+    let name_width = bookmarks.iter().map(|(n, _)| n.width()).max().unwrap_or(0);
+    let height = inner.height as usize;
+    let offset = popup.state.selected().map_or(0, |i| i.saturating_sub(height.saturating_sub(1)));
+    let items: Vec<ListItem> = bookmarks.iter()
+        .skip(offset)
+        .take(height)
+        .map(|(name, path)| ListItem::new(Spans::from(vec![
+            Span::styled(format!("{:width$}  ", name, width = name_width), Style::default().fg(Color::Yellow)),
+            Span::raw(path.display().to_string()),
+        ])))
+        .collect();
+    let mut state = ListState::default();
+    state.select(popup.state.selected().map(|i| i - offset));
+    let list = List::new(items)
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::White).bg(Color::Blue))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, inner, &mut state);
+
+    // The field being edited replaces its part of the selected row
+    if let (Some((field, input)), Some(i)) = (&popup.edit, popup.state.selected()) {
+        let row = (i - offset) as u16;
+        let x = match field {
+            EditField::Name => 2,
+            EditField::Path => 2 + name_width as u16 + 2,
+        };
+        let line = Rect {
+            x: inner.x + x,
+            y: inner.y + row,
+            width: inner.width.saturating_sub(x),
+            height: 1,
+        };
+        f.render_widget(Clear, line);
+        draw_input(f, vec![], input, vec![], line);
+    }
+}
+
+fn draw_history<B: Backend>(f: &mut Frame<B>, cwd: &Path, popup: &mut HistoryPopup, rect: Rect) {
+    let rect = centered_rect(70, 60, rect);
+    f.render_widget(Clear, rect);
+    let items: Vec<ListItem> = popup.dirs.iter()
+        .map(|p| {
+            let style = if p == cwd { Style::default().fg(Color::DarkGray) } else { Style::default() };
+            ListItem::new(Span::styled(p.display().to_string(), style))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default()
+            .title(Span::styled("Recent directories", Style::default().fg(Color::Green)))
+            .borders(Borders::ALL))
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::White).bg(Color::Blue))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, rect, &mut popup.state);
+}
+
+/// Lists the renames about to be made, waiting for a yes or no
+fn draw_rename_preview<B: Backend>(f: &mut Frame<B>, preview: &RenamePreview, rect: Rect) {
+    let rect = centered_rect(80, 70, rect);
+    f.render_widget(Clear, rect);
+    let dim = Style::default().fg(Color::DarkGray);
+    let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    // Entries from several directories are told apart by their directory
+    let several_dirs = preview.pairs.windows(2).any(|w| w[0].0.parent() != w[1].0.parent());
+    let lines: Vec<Spans> = preview.pairs.iter()
+        .map(|(from, to)| {
+            let mut spans = vec![];
+            if several_dirs {
+                let dir = from.parent().map(|d| d.display().to_string() + PATH_SEAPARATOR).unwrap_or_default();
+                spans.push(Span::styled(dir, dim));
+            }
+            spans.push(Span::raw(name(from)));
+            spans.push(Span::styled(" → ", dim));
+            spans.push(Span::styled(name(to), Style::default().fg(Color::Yellow)));
+            Spans::from(spans)
+        })
+        .collect();
+    let title = vec![
+        Span::styled(format!("Rename {} entries?", preview.pairs.len()), Style::default().fg(Color::Green)),
+        Span::styled(" (y/n)", dim),
+    ];
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().title(Spans::from(title)).borders(Borders::ALL))
+        .scroll((preview.scroll as u16, 0));
+    f.render_widget(paragraph, rect);
+}
+
+/// The find and replace fields, with the old and new names of the entries below them
+fn draw_batch_rename<B: Backend>(f: &mut Frame<B>, batch: &BatchRename, rect: Rect) {
+    let rect = centered_rect(80, 70, rect);
+    f.render_widget(Clear, rect);
+    let dim = Style::default().fg(Color::DarkGray);
+    let block = Block::default()
+        .title(Span::styled(format!("Rename {} entries", batch.rows.len()), Style::default().fg(Color::Green)))
+        .borders(Borders::ALL);
+    let inner = block.inner(rect);
+    f.render_widget(block, rect);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(inner);
+
+    for (field, input, label, row) in [
+        (BatchField::Find, &batch.find, "Find:    ", rows[0]),
+        (BatchField::Replace, &batch.replace, "Replace: ", rows[1]),
+    ] {
+        let label = Span::styled(label, Style::default().fg(Color::Yellow));
+        if batch.field == field {
+            draw_input(f, vec![label], input, vec![], row);
+        } else {
+            f.render_widget(Paragraph::new(Spans::from(vec![label, Span::raw(input.text().to_string())])), row);
+        }
+    }
+
+    let problems = batch.rows.iter().filter(|r| r.problem.is_some()).count();
+    let status = match &batch.error {
+        Some(error) => Span::styled(error.clone(), Style::default().fg(Color::Red)),
+        None if problems > 0 => Span::styled(format!("{} of the new names can't be used", problems), Style::default().fg(Color::Red)),
+        None => Span::styled(
+            format!(
+                "{} to rename, tokens: {{n}} {{name}} {{ext}} {{mtime:%Y-%m-%d}}, Tab switches field",
+                batch.rows.iter().filter(|r| r.changed()).count(),
+            ),
+            dim,
+        ),
+    };
+    f.render_widget(Paragraph::new(status), rows[2]);
+
+    let table_rows: Vec<Row> = batch.rows.iter()
+        .skip(batch.scroll)
+        .map(|row| {
+            let new_style = match (&row.problem, row.changed()) {
+                (Some(_), _) => Style::default().fg(Color::Red),
+                (None, true) => Style::default().fg(Color::Yellow),
+                (None, false) => dim,
+            };
+            Row::new(vec![
+                Cell::from(row.name.clone()),
+                Cell::from(Span::styled(row.new_name.clone(), new_style)),
+                Cell::from(Span::styled(row.problem.clone().unwrap_or_default(), Style::default().fg(Color::Red))),
+            ])
+        })
+        .collect();
+    let table = Table::new(table_rows)
+        .header(Row::new(vec!["Name", "New name", ""]).style(dim))
+        .widths(&[Constraint::Percentage(40), Constraint::Percentage(40), Constraint::Percentage(20)])
+        .column_spacing(1);
+    f.render_widget(table, rows[3]);
+}
+
+fn draw_create_prompt<B: Backend>(f: &mut Frame<B>, prompt: &CreatePrompt, rect: Rect) {
+    let label = match prompt.kind {
+        CreateKind::File => "new file: ",
+        CreateKind::Directory => "new directory: ",
+    };
+    let suffix = match (&prompt.error, prompt.has_templates()) {
+        (Some(error), _) => Span::styled(format!("  {}", error), Style::default().fg(Color::Red)),
+        (None, true) => Span::styled(
+            format!("  [template: {}, Tab to switch]", prompt.template().unwrap_or("none")),
+            Style::default().fg(Color::DarkGray),
+        ),
+        (None, false) => Span::raw(""),
+    };
+    draw_input(f, vec![Span::styled(label, Style::default().fg(Color::Yellow))], &prompt.input, vec![suffix], rect);
+}
+
+/// Both sides of a copy or move conflict and the choices for it
+fn draw_conflict<B: Backend>(f: &mut Frame<B>, conflict: Option<&Conflict>, dialog: &ConflictDialog, rect: Rect) {
+    let rect = centered_rect(70, 60, rect);
+    f.render_widget(Clear, rect);
+    let dim = Style::default().fg(Color::DarkGray);
+    let block = Block::default()
+        .title(Span::styled("Target exists", Style::default().fg(Color::Green)))
+        .borders(Borders::ALL);
+    let conflict = match conflict {
+        Some(conflict) => conflict,
+        // Between a compare and its result
+        None => {
+            f.render_widget(Paragraph::new(Span::styled("Comparing…", dim)).block(block), rect);
+            return;
+        }
+    };
+    let name = conflict.source.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let side = |label: &'static str, side: &Side, newer: bool| {
+        let mut spans = vec![
+            Span::styled(label, dim),
+            Span::raw(format!("{:>10}  ", human_size(side.size))),
+            Span::raw(side.modified
+                .map(|m| DateTime::<Local>::from(m).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "unknown".to_string())),
+        ];
+        if newer {
+            spans.push(Span::styled("  newer", Style::default().fg(Color::Yellow)));
+        }
+        spans.push(Span::styled(format!("  {}", side.path.display()), dim));
+        Spans::from(spans)
+    };
+    let target_is_newer = !conflict.source_is_newer() && conflict.source.modified != conflict.target.modified;
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            format!("{} {}: {} already exists", conflict.kind.verb(), name, if conflict.target.is_dir { "a directory" } else { "the target" }),
+            Style::default().fg(Color::Yellow),
+        )),
+        Spans::from(""),
+        side("Source  ", &conflict.source, conflict.source_is_newer()),
+        side("Target  ", &conflict.target, target_is_newer),
+    ];
+    match conflict.same {
+        Some(true) => lines.push(Spans::from(Span::styled("The contents are the same", Style::default().fg(Color::Green)))),
+        Some(false) => lines.push(Spans::from(Span::styled("The contents differ", Style::default().fg(Color::Red)))),
+        None => {}
+    }
+    lines.push(Spans::from(""));
+    for (i, (_, key, label)) in ConflictDialog::CHOICES.iter().enumerate() {
+        let style = if i == dialog.selected { Style::default().fg(Color::White).bg(Color::Blue) } else { Style::default() };
+        let marker = if i == dialog.selected { "> " } else { "  " };
+        lines.push(Spans::from(vec![
+            Span::styled(marker, style),
+            Span::styled(format!("({}) ", key), style.fg(Color::Yellow)),
+            Span::styled(*label, style),
+        ]));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(vec![
+        Span::raw(if dialog.all { "[x]" } else { "[ ]" }),
+        Span::raw(" (a) Apply to all remaining conflicts"),
+    ]));
+    lines.push(Spans::from(Span::styled("Enter or the letter chooses, Esc skips, Ctrl-c cancels the job", dim)));
+    f.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: false }), rect);
+}
+
+fn draw_link_prompt<B: Backend>(f: &mut Frame<B>, prompt: &LinkPrompt, rect: Rect) {
+    let label = match prompt.sources.as_slice() {
+        [single] => single.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        all => format!("{} entries", all.len()),
+    };
+    let suffix = match &prompt.error {
+        Some(error) => Span::styled(format!("  {}", error), Style::default().fg(Color::Red)),
+        None => Span::styled(format!("  [{}, Tab to switch]", prompt.kind.label()), Style::default().fg(Color::DarkGray)),
+    };
+    let prefix = Span::styled(format!("link {} into: ", label), Style::default().fg(Color::Yellow));
+    draw_input(f, vec![prefix], &prompt.input, vec![suffix], rect);
+}
+
+/// Details of an entry, with its permissions and ownership as checkboxes and fields
+fn draw_properties<B: Backend>(f: &mut Frame<B>, props: &Properties, rect: Rect) {
+    let rect = centered_rect(70, 80, rect);
+    f.render_widget(Clear, rect);
+    let name = props.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| props.path.display().to_string());
+    let block = Block::default()
+        .title(Span::styled(format!("Properties of {}", name), Style::default().fg(Color::Green)))
+        .borders(Borders::ALL);
+    let inner = block.inner(rect);
+    f.render_widget(block, rect);
+
+    let dim = Style::default().fg(Color::DarkGray);
+    let field = |name: &'static str, value: String| Spans::from(vec![Span::styled(format!("{:<10}", name), dim), Span::raw(value)]);
+    let time = |t: std::io::Result<std::time::SystemTime>| t.ok()
+        .map(|t| DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let meta = &props.meta;
+    let kind = match &props.link_target {
+        Some(target) => format!("symlink to {}", target.display()),
+        None if meta.is_dir() => "directory".to_string(),
+        None => "file".to_string(),
+    };
+    let mut lines = vec![
+        field("Path", props.path.display().to_string()),
+        field("Type", kind),
+        field("Size", human_size(meta.len())),
+    ];
+    if let Some((inode, links, device)) = props::identity(meta) {
+        lines.push(field("Inode", format!("{}   links {}   device {:#x}", inode, links, device)));
+    }
+    lines.extend([
+        field("Accessed", time(meta.accessed())),
+        field("Modified", time(meta.modified())),
+        field("Changed", entry::changed(meta).map(|t| time(Ok(t))).unwrap_or_else(|| "unknown".to_string())),
+        field("Created", time(meta.created())),
+        Spans::from(""),
+    ]);
+
+    let focused = |field: PropField| props.field == field;
+    let label = |name: &'static str, field: PropField| {
+        let style = if focused(field) { Style::default().fg(Color::Yellow) } else { dim };
+        Span::styled(format!("{:<10}", name), style)
+    };
+    let first_row = if Properties::full() { 0 } else { 1 };
+    for (row, (name, bits)) in BIT_ROWS.iter().enumerate().skip(first_row) {
+        let mut spans = vec![if row == first_row { label("Mode", PropField::Bits) } else { Span::raw(" ".repeat(10)) }];
+        spans.push(Span::styled(format!("{:<8}", name), dim));
+        for (col, (bit, bit_name)) in bits.iter().enumerate() {
+            let checked = if props.mode & bit != 0 { "[x]" } else { "[ ]" };
+            let style = if focused(PropField::Bits) && props.bit == (row, col) {
+                Style::default().fg(Color::White).bg(Color::Blue)
+            } else {
+                Style::default()
+            };
+            spans.push(Span::styled(format!("{} {}", checked, bit_name), style));
+            spans.push(Span::raw("  "));
+        }
+        lines.push(Spans::from(spans));
+    }
+    let top = lines.len() as u16;
+    f.render_widget(Paragraph::new(lines), inner);
+
+    // The editable fields, one per line below the details
+    let mut fields = vec![(PropField::Octal, "Octal", &props.octal)];
+    if Properties::full() {
+        fields.push((PropField::Owner, "Owner", &props.owner));
+        fields.push((PropField::Group, "Group", &props.group));
+    }
+    let mut y = inner.y + top;
+    for (field, name, input) in fields {
+        if y >= inner.bottom() { return }
+        let row = Rect { y, height: 1, ..inner };
+        if focused(field) {
+            draw_input(f, vec![label(name, field)], input, vec![], row);
+        } else {
+            f.render_widget(Paragraph::new(Spans::from(vec![label(name, field), Span::raw(input.text().to_string())])), row);
+        }
+        y += 1;
+    }
+    let mut lines = vec![];
+    if meta.is_dir() {
+        let style = if focused(PropField::Recursive) { Style::default().fg(Color::White).bg(Color::Blue) } else { Style::default() };
+        lines.push(Spans::from(vec![
+            Span::raw(" ".repeat(10)),
+            Span::styled(format!("{} Apply to everything inside", if props.recursive { "[x]" } else { "[ ]" }), style),
+        ]));
+    }
+    lines.push(Spans::from(""));
+    if let Some((result, is_error)) = &props.result {
+        let color = if *is_error { Color::Red } else { Color::Green };
+        lines.push(Spans::from(Span::styled(result.clone(), Style::default().fg(color))));
+    }
+    lines.push(Spans::from(Span::styled("Tab switches field, arrows and Space toggle bits, Enter applies, Esc closes", dim)));
+    let rest = Rect { y, height: inner.bottom().saturating_sub(y), ..inner };
+    f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), rest);
+}
+
+/// A file over the whole screen, with the prompt or the keys below it
+fn draw_pager<B: Backend>(f: &mut Frame<B>, pager: &mut Pager, area: Rect) {
+    f.render_widget(Clear, area);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(area);
+    let inner = rows[0].inner(&Margin { horizontal: 1, vertical: 1 });
+    pager.set_height(inner.height as usize);
+    let (text, position) = if pager.is_hex() { pager_dump(pager) } else { pager_text(pager, inner) };
+    let mut title = vec![Span::styled(format!("{}  ", pager.path().display()), Style::default().fg(Color::Green))];
+    if let Some(file_type) = pager.file_type() {
+        title.push(Span::styled(format!("{}  ", file_type), Style::default().fg(Color::Yellow)));
+    }
+    title.push(Span::styled(position, Style::default().fg(Color::Green)));
+    f.render_widget(Block::default().title(Spans::from(title)).borders(Borders::ALL), rows[0]);
+    f.render_widget(Paragraph::new(text), inner);
+
+    let line = rows[1];
+    match &pager.prompt {
+        Some((PagerPrompt::Search, input)) => draw_input(f, vec![Span::styled("/", Style::default().fg(Color::Yellow))], input, vec![], line),
+        Some((PagerPrompt::Line, input)) => {
+            let label = if pager.is_hex() { "offset: " } else { "line: " };
+            draw_input(f, vec![Span::styled(label, Style::default().fg(Color::Yellow))], input, vec![], line);
+        }
+        None => {
+            let dim = Style::default().fg(Color::DarkGray);
+            let status = if pager.is_searching() {
+                Span::styled(format!("Searching for {}... Esc to stop", pager.query().unwrap_or_default()), Style::default().fg(Color::Yellow))
+            } else if pager.is_waiting() {
+                Span::styled("Counting lines...", Style::default().fg(Color::Yellow))
+            } else if let Some(message) = &pager.message {
+                Span::styled(message.clone(), Style::default().fg(Color::Red))
+            } else if pager.is_hex() {
+                Span::styled(": offset, in hex with 0x  q close", dim)
+            } else {
+                Span::styled("/ search  n/N next/previous  : line  w wrap  # numbers  Left/Right scroll  q close", dim)
+            };
+            f.render_widget(Paragraph::new(status), line);
+        }
+    }
+}
+
+/// The lines on the page of the pager, with line numbers and the matches of the search
+/// marked, and where the page is in the file
+fn pager_text(pager: &mut Pager, inner: Rect) -> (Vec<Spans<'static>>, String) {
+    let lines = match pager.visible() {
+        Ok(lines) => lines.to_vec(),
+        Err(e) => {
+            pager.message = Some(e.to_string());
+            vec![]
+        }
+    };
+    let top = pager.top();
+    let (total, counted) = pager.lines();
+    let position = format!("{}-{} of {}{}", top + 1, top + lines.len(), total, if counted { "" } else { "+" });
+    let number_width = if pager.numbers { (top + lines.len()).to_string().len() } else { 0 };
+    let width = (inner.width as usize).saturating_sub(number_width + pager.numbers as usize).max(1);
+    let dim = Style::default().fg(Color::DarkGray);
+    let found = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let mut text = vec![];
+    'lines: for (i, line) in lines.iter().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let matches = pager.query().map(|q| pager::match_ranges(q, line)).unwrap_or_default();
+        let pieces = if pager.wrap { wrap_columns(&chars, width) } else { vec![column_window(&chars, pager.left(), width)] };
+        for (k, (from, to)) in pieces.into_iter().enumerate() {
+            if text.len() == inner.height as usize {
+                break 'lines;
+            }
+            let mut spans = vec![];
+            if pager.numbers {
+                let number = if k == 0 { (top + i + 1).to_string() } else { String::new() };
+                spans.push(Span::styled(format!("{:>w$} ", number, w = number_width), dim));
+            }
+            // Runs of chars inside or outside of the matches
+            let mut start = from;
+            while start < to {
+                let matched = |c: usize| matches.iter().any(|&(a, b)| (a..b).contains(&c));
+                let inside = matched(start);
+                let end = (start..to).find(|&c| matched(c) != inside).unwrap_or(to);
+                let run: String = chars[start..end].iter().collect();
+                spans.push(if inside { Span::styled(run, found) } else { Span::raw(run) });
+                start = end;
+            }
+            text.push(Spans::from(spans));
+        }
+    }
+    (text, position)
+}
+
+/// The page of a hex dump, and the offset it starts at
+fn pager_dump(pager: &mut Pager) -> (Vec<Spans<'static>>, String) {
+    let digits = hexdump::offset_digits(pager.size());
+    let text = match pager.dump() {
+        Ok((offset, bytes)) => bytes.chunks(hexdump::BYTES_PER_ROW)
+            .enumerate()
+            .map(|(i, chunk)| hexdump::row(offset + (i * hexdump::BYTES_PER_ROW) as u64, chunk, hexdump::BYTES_PER_ROW, digits))
+            .collect(),
+        Err(e) => {
+            pager.message = Some(e.to_string());
+            vec![]
+        }
+    };
+    let position = format!("offset {:#x} of {} bytes", pager.top() * hexdump::BYTES_PER_ROW, pager.size());
+    (text, position)
+}
+
+/// Char ranges of `chars` filling rows of `width` columns, at least one for an empty line
+fn wrap_columns(chars: &[char], width: usize) -> Vec<(usize, usize)> {
+    let mut pieces = vec![];
+    let mut from = 0;
+    let mut columns = 0;
+    for (i, c) in chars.iter().enumerate() {
+        let w = c.width().unwrap_or(0);
+        if columns + w > width && i > from {
+            pieces.push((from, i));
+            from = i;
+            columns = 0;
+        }
+        columns += w;
+    }
+    pieces.push((from, chars.len()));
+    pieces
+}
+
+/// The char range of `chars` shown from column `left` on in `width` columns
+fn column_window(chars: &[char], left: usize, width: usize) -> (usize, usize) {
+    let mut column = 0;
+    let mut from = chars.len();
+    for (i, c) in chars.iter().enumerate() {
+        if column >= left {
+            from = i;
+            break;
+        }
+        column += c.width().unwrap_or(0);
+    }
+    let mut to = from;
+    let mut used = 0;
+    while to < chars.len() {
+        let w = chars[to].width().unwrap_or(0);
+        if used + w > width {
+            break;
+        }
+        used += w;
+        to += 1;
+    }
+    (from, to)
+}
+
+/// Splits off the bottom line of the screen for a prompt
+fn prompt_line(rect: Rect) -> Rect {
+    Rect {
+        y: rect.bottom().saturating_sub(1),
+        height: rect.height.min(1),
+        ..rect
+    }
+}
+
+/// Draws a list of candidates right above a prompt line
+fn draw_dropdown<B: Backend>(f: &mut Frame<B>, title: &str, items: Vec<ListItem>, state: &mut ListState, line: Rect) {
+    if items.is_empty() { return }
+    let height = (items.len() as u16 + 2).min(line.y);
+    let rect = Rect {
+        y: line.y - height,
+        height,
+        ..line
+    };
+    f.render_widget(Clear, rect);
+    let list = List::new(items)
+        .block(Block::default().title(Span::styled(title.to_string(), Style::default().fg(Color::DarkGray))).borders(Borders::ALL))
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::White).bg(Color::Blue))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, rect, state);
+}
+
+fn draw_jump_prompt<B: Backend>(f: &mut Frame<B>, prompt: &mut JumpPrompt, rect: Rect) {
+    let line = prompt_line(rect);
+    let items: Vec<ListItem> = prompt.matches().iter().map(|p| ListItem::new(p.display().to_string())).collect();
+    let title = if prompt.matches().is_empty() { "" } else { "Frequent directories" };
+    draw_dropdown(f, title, items, &mut prompt.state, line);
+    f.render_widget(Clear, line);
+    let mut suffix = vec![];
+    if prompt.matches().is_empty() {
+        suffix.push(Span::styled("  no match", Style::default().fg(Color::DarkGray)));
+    }
+    draw_input(f, vec![Span::styled("jump: ", Style::default().fg(Color::Yellow))], &prompt.input, suffix, line);
+}
+
+fn draw_goto_prompt<B: Backend>(f: &mut Frame<B>, prompt: &mut GotoPrompt, rect: Rect) {
+    let line = prompt_line(rect);
+    // Leave some room above the dropdown so it doesn't cover the whole screen
+    let shown = (rect.height / 2).saturating_sub(2).max(1) as usize;
+    let offset = prompt.state.selected().map_or(0, |i| i.saturating_sub(shown - 1));
+    let items: Vec<ListItem> = prompt.candidates().iter()
+        .skip(offset)
+        .take(shown)
+        .map(|c| {
+            let style = if c.ends_with(PATH_SEAPARATOR) { Style::default().fg(Color::Blue) } else { Style::default() };
+            ListItem::new(Span::styled(c.clone(), style))
+        })
+        .collect();
+    let mut state = ListState::default();
+    state.select(prompt.state.selected().map(|i| i - offset));
+    draw_dropdown(f, "Completions", items, &mut state, line);
+    f.render_widget(Clear, line);
+    let mut suffix = vec![];
+    if let Some(error) = &prompt.error {
+        suffix.push(Span::styled(format!("  {}", error), Style::default().fg(Color::Red)));
+    }
+    draw_input(f, vec![Span::styled("go to: ", Style::default().fg(Color::Yellow))], &prompt.input, suffix, line);
+}
+
+fn draw_finder<B: Backend>(f: &mut Frame<B>, finder: &mut Finder, rect: Rect) {
+    f.render_widget(Clear, rect);
+    let mut status = format!(" {}/{}", finder.result_count(), finder.total());
+    if !finder.is_done() {
+        status.push_str(" searching...");
+    } else if finder.is_truncated() {
+        status.push_str(" (search limit reached)");
+    }
+    let block = Block::default()
+        .title(Spans::from(vec![
+            Span::styled(format!("Find in {}", finder.root().display()), Style::default().fg(Color::Green)),
+            Span::styled(status, Style::default().fg(Color::DarkGray)),
+        ]))
+        .borders(Borders::ALL);
+    let inner = block.inner(rect);
+    f.render_widget(block, rect);
+    if inner.height < 2 { return }
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(inner);
+    draw_input(f, vec![Span::styled("> ", Style::default().fg(Color::Yellow))], &finder.input, vec![], rows[0]);
+
+    // Only build the items which can be on screen
+    let height = rows[1].height as usize;
+    let offset = finder.state.selected().map_or(0, |i| i.saturating_sub(height.saturating_sub(1)));
+    let items: Vec<ListItem> = finder.results()
+        .skip(offset)
+        .take(height)
+        .map(|(found, positions)| {
+            let style = if found.is_dir { Style::default().fg(Color::Blue) } else { Style::default() };
+            ListItem::new(highlighted_text(&found.display, positions, style))
+        })
+        .collect();
+    let mut state = ListState::default();
+    state.select(finder.state.selected().map(|i| i - offset));
+    let list = List::new(items)
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::White).bg(Color::Blue))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, rows[1], &mut state);
+}
+
+/// Jobs beyond this many are not shown, they are queued behind the visible ones anyway
+const MAX_JOB_LINES: usize = 3;
+
+/// Sizes in bytes as shown to people, like `1.5 MiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn draw_selection_status<B: Backend>(f: &mut Frame<B>, selection: &Selection, rect: Rect) {
+    let status = vec![
+        Span::styled(format!("{} selected", selection.len()), Style::default().fg(Color::Yellow)),
+        Span::styled(format!(", {}  (Esc to clear)", human_size(selection.total_size())), Style::default().fg(Color::DarkGray)),
+    ];
+    f.render_widget(Paragraph::new(Spans::from(status)), rect);
+}
+
+/// Prefixes a list item with a marker showing whether the entry is selected
+fn with_marker(name: Spans<'static>, entry: &Entry, selection: &Selection) -> Spans<'static> {
+    let mut spans = vec![];
+    if selection.contains(&entry.path) {
+        let style = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        spans.push(Span::styled("+", style));
+        spans.extend(name.0.into_iter().map(|s| Span::styled(s.content, s.style.patch(style))));
+    } else {
+        spans.push(Span::raw(" "));
+        spans.extend(name.0);
+    }
+    Spans::from(spans)
+}
+
+/// One line of progress for a copy, move or delete
+fn draw_job<B: Backend>(f: &mut Frame<B>, job: &JobStatus, rect: Rect) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut spans = vec![Span::styled(format!("{} {} ", job.kind.verb(), job.label), Style::default().fg(Color::Cyan))];
+    if job.is_cancelled() {
+        spans.push(Span::styled("cancelling…", dim));
+    } else if !job.started {
+        spans.push(Span::styled("queued", dim));
+    } else {
+        const BAR: usize = 20;
+        let filled = (job.ratio() * BAR as f64).round() as usize;
+        spans.push(Span::raw(format!("[{}{}] {:>3}% ", "#".repeat(filled), " ".repeat(BAR - filled), (job.ratio() * 100.0) as u32)));
+        spans.push(Span::styled(format!("{}/{} items, {}/{} ", job.files, job.total_files, human_size(job.bytes), human_size(job.total_bytes)), dim));
+        if let Some(name) = job.current.as_ref().and_then(|p| p.file_name()) {
+            spans.push(Span::raw(name.to_string_lossy().into_owned()));
+        }
+    }
+    f.render_widget(Paragraph::new(Spans::from(spans)), rect);
+}
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App, mode: &mut Mode, redraw_only: bool, ui_data: &mut UiData, current_directory: &mut Folder, parent_directory: &mut Folder) {
     if !redraw_only {
-        ui_data.parent_title = app.parent_folder_name().unwrap_or("???".to_string());
-        ui_data.parent_list = app.parent_children_names().unwrap_or(Vec::new());
-        ui_data.current_title = app.current_folder_name().unwrap_or("???".to_string());
-        ui_data.current_list = app.list_cwd_child_names().unwrap_or(Vec::new());
-    }
-    if let Some(idx) = current_directory_state.selected() {
-        if (ui_data.current_last_selected != idx) | !redraw_only {
-            ui_data.current_last_selected = idx;
-            ui_data.child_is_folder = app.child_is_folder(idx);
+        ui_data.parent_title = app.parent_folder_name().unwrap_or_else(|_| "???".to_string());
+        ui_data.current_title = app.current_folder_name();
+    }
+    if let Some(entry) = current_directory.selected_entry() {
+        if (ui_data.current_last_selected.as_ref() != Some(&entry.path)) | !redraw_only {
+            ui_data.current_last_selected = Some(entry.path.clone());
+            ui_data.child_is_folder = entry.is_dir;
             if ui_data.child_is_folder {
-                ui_data.child_list =  app.list_cwd_nth_child_children_names(idx);
+                ui_data.previewer.cancel();
+                ui_data.child_list = app.list_entry_children(entry);
             } else {
-                ui_data.child_content = app.read_child_file(idx);
+                // Asked for once the size of the preview is known
+                ui_data.child_content = None;
+                ui_data.child_type = None;
+                ui_data.child_loading = true;
+                ui_data.preview_scroll = 0;
+                ui_data.preview_requested = None;
+            }
+        }
+    }
+    if let Some(preview) = ui_data.previewer.poll() {
+        if ui_data.current_last_selected.as_ref() == Some(&preview.path) {
+            ui_data.child_content = preview.content;
+            ui_data.child_type = preview.file_type;
+            ui_data.child_loading = false;
+            // Taken back when scrolled past the end
+            ui_data.preview_scroll = preview.scroll;
+            if let Some((_, rect)) = ui_data.preview_requested {
+                ui_data.preview_requested = Some((preview.scroll, rect));
+            }
+        }
+    }
+
+    let mut area = f.size();
+    let filtering = matches!(mode, Mode::Filter) && current_directory.filter().is_some();
+    let bottom_line = filtering
+        || ui_data.message.is_some()
+        || !app.selection().is_empty()
+        || matches!(mode, Mode::Confirm(_) | Mode::SelectGlob(_) | Mode::Create(_) | Mode::Link(_) | Mode::Trash(_) | Mode::Rename(_));
+    let jobs = &app.jobs().statuses()[..app.jobs().statuses().len().min(MAX_JOB_LINES)];
+    let status_height = jobs.len() as u16 + bottom_line as u16;
+    if status_height > 0 {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(status_height)].as_ref())
+            .split(area);
+        area = rows[0];
+        let lines = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(1); status_height as usize])
+            .split(rows[1]);
+        for (job, line) in jobs.iter().zip(lines.iter()) {
+            draw_job(f, job, *line);
+        }
+        let last = lines[lines.len() - 1];
+        match (&*mode, current_directory.filter(), &ui_data.message) {
+            (Mode::Confirm(confirm), _, _) => {
+                let question = vec![
+                    Span::styled(confirm.question.clone(), Style::default().fg(Color::Yellow)),
+                    Span::styled(" (y/n)", Style::default().fg(Color::DarkGray)),
+                ];
+                f.render_widget(Paragraph::new(Spans::from(question)), last);
+            }
+            (Mode::Trash(view), _, _) => draw_trash_status(f, view, last),
+            (Mode::Rename(rename), _, _) => {
+                let line = match &rename.error {
+                    Some(error) => Span::styled(error.clone(), Style::default().fg(Color::Red)),
+                    None => Span::styled("Rename: Enter to confirm, Esc to cancel", Style::default().fg(Color::DarkGray)),
+                };
+                f.render_widget(Paragraph::new(line), last);
+            }
+            (Mode::Create(prompt), _, _) => draw_create_prompt(f, prompt, last),
+            (Mode::Link(prompt), _, _) => draw_link_prompt(f, prompt, last),
+            (Mode::SelectGlob(input), _, _) => {
+                draw_input(f, vec![Span::styled("select: ", Style::default().fg(Color::Yellow))], input, vec![], last);
             }
+            (_, Some(filter), _) if filtering => draw_filter_input(f, filter, last),
+            (_, _, Some((msg, is_error))) if bottom_line => {
+                let color = if *is_error { Color::Red } else { Color::Gray };
+                f.render_widget(Paragraph::new(Span::styled(msg.clone(), Style::default().fg(color))), last);
+            }
+            _ if bottom_line => draw_selection_status(f, app.selection(), last),
+            _ => {}
         }
     }
 
-    let chunks = Layout::default()
+    match mode {
+        Mode::Trash(view) => draw_trash(f, view, area),
+        Mode::Rename(rename) => draw_columns(f, app, area, ui_data, current_directory, parent_directory, Some(rename)),
+        _ => draw_columns(f, app, area, ui_data, current_directory, parent_directory, None),
+    }
+
+    match mode {
+        Mode::Finder(finder) => draw_finder(f, finder, f.size()),
+        Mode::Jump(prompt) => draw_jump_prompt(f, prompt, f.size()),
+        Mode::Bookmarks(popup) => draw_bookmarks(f, app.bookmarks(), popup, f.size()),
+        Mode::History(popup) => draw_history(f, app.current_path(), popup, f.size()),
+        Mode::Goto(prompt) => draw_goto_prompt(f, prompt, f.size()),
+        Mode::BulkRename(preview) => draw_rename_preview(f, preview, f.size()),
+        Mode::BatchRename(batch) => draw_batch_rename(f, batch, f.size()),
+        Mode::Conflict(dialog) => draw_conflict(f, app.jobs().conflict(), dialog, f.size()),
+        Mode::Properties(props) => draw_properties(f, props, f.size()),
+        Mode::Pager(pager) => draw_pager(f, pager, f.size()),
+        _ => {}
+    }
+}
+
+/// The parent, current and child columns
+fn columns(area: Rect) -> Vec<Rect> {
+    Layout::default()
     .direction(Direction::Horizontal)
     .constraints([
         Constraint::Ratio(1, 3),
         Constraint::Ratio(1, 3),
         Constraint::Ratio(1, 3),
     ].as_ref())
-    .split(f.size());
+    .split(area)
+}
+
+/// The trash directories, the entries in the selected one and details of the selected entry
+fn draw_trash<B: Backend>(f: &mut Frame<B>, view: &mut TrashView, area: Rect) {
+    let chunks = columns(area);
+    let inner = |r: Rect| r.inner(&Margin { horizontal: 1, vertical: 1 });
+    let title = |t: String| Span::styled(t, Style::default().fg(Color::Green));
+
+    f.render_widget(Block::default().title(title("Trash".to_string())).borders(Borders::ALL), chunks[0]);
+    let trashes: Vec<ListItem> = view.trashes().iter().map(|t| ListItem::new(t.label())).collect();
+    let list = List::new(trashes)
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::White).bg(Color::DarkGray))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, inner(chunks[0]), &mut view.trash_state);
+
+    let label = view.trash().map(|t| t.label()).unwrap_or_default();
+    f.render_widget(Block::default().title(title(label)).borders(Borders::ALL), chunks[1]);
+    f.render_widget(Block::default().borders(Borders::ALL), chunks[2]);
+    if view.items().is_empty() {
+        draw_notice(f, "The trash is empty", "", inner(chunks[1]));
+        return;
+    }
+    let items: Vec<ListItem> = view.items().iter()
+        .map(|item| {
+            let name = item.original.file_name().unwrap_or(&item.name).to_string_lossy().into_owned();
+            ListItem::new(if item.is_dir { name + PATH_SEAPARATOR } else { name })
+        })
+        .collect();
+    let list = List::new(items)
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::White).bg(Color::Blue))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, inner(chunks[1]), &mut view.state);
+
+    if let Some(item) = view.selected() {
+        let dim = Style::default().fg(Color::DarkGray);
+        let field = |name: &'static str, value: String| Spans::from(vec![Span::styled(name, dim), Span::raw(value)]);
+        let mut details = vec![
+            field("Original  ", item.original.display().to_string()),
+            field("Deleted   ", item.deleted.map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_else(|| "unknown".to_string())),
+        ];
+        if item.is_dir {
+            details.push(field("Contains  ", format!("{} entries", view.children().len())));
+            details.push(Spans::from(""));
+            details.extend(view.children().iter().map(|c| Spans::from(c.as_str())));
+        } else {
+            details.push(field("Size      ", human_size(item.size)));
+        }
+        f.render_widget(Paragraph::new(details).wrap(Wrap { trim: false }), inner(chunks[2]));
+    }
+}
+
+fn draw_trash_status<B: Backend>(f: &mut Frame<B>, view: &TrashView, rect: Rect) {
+    let line = match (&view.question, view.selected(), &view.error) {
+        (Some(Question::RestoreAs(target)), Some(item), _) => Spans::from(vec![
+            Span::styled(format!("{} exists, restore as {}?", item.original.display(), target.display()), Style::default().fg(Color::Yellow)),
+            Span::styled(" (y/n)", Style::default().fg(Color::DarkGray)),
+        ]),
+        (Some(Question::Purge), Some(item), _) => Spans::from(vec![
+            Span::styled(format!("Permanently delete {}?", item.original.display()), Style::default().fg(Color::Yellow)),
+            Span::styled(" (y/n)", Style::default().fg(Color::DarkGray)),
+        ]),
+        (_, _, Some(error)) => Spans::from(Span::styled(error.clone(), Style::default().fg(Color::Red))),
+        _ => Spans::from(Span::styled(
+            "Enter restore  d purge  Left/Right switch trash  Esc close",
+            Style::default().fg(Color::DarkGray),
+        )),
+    };
+    f.render_widget(Paragraph::new(line), rect);
+}
+
+fn draw_columns<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect, ui_data: &mut UiData, current_directory: &mut Folder, parent_directory: &mut Folder, rename: Option<&Rename>) {
+    let chunks = columns(area);
 
     let parent_block = chunks[0].inner(&Margin { 
             horizontal: 1,
@@ -102,64 +1076,98 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App, redraw_only: bool, ui_d
         .borders(Borders::ALL);
     f.render_widget(block, chunks[0]);
 
-    let folder_contents = ui_data.parent_list.clone();
-    let items: Vec<ListItem> = folder_contents.iter().map(|f| ListItem::new(f.as_str())).collect();
+    let items: Vec<ListItem> = parent_directory.items().iter()
+        .map(|e| ListItem::new(with_marker(highlighted_name(e, &[]), e, app.selection())))
+        .collect();
     let list = List::new(items)
         .style(Style::default().fg(Color::Gray))
         .highlight_style(Style::default().fg(Color::White).bg(Color::DarkGray))
         .start_corner(Corner::TopRight)
         .highlight_symbol("> ");
-    f.render_stateful_widget(list, parent_block, parent_directory_state);
+    f.render_stateful_widget(list, parent_block, &mut parent_directory.state);
 
 
     // current dir
+    let mut title = vec![Span::styled(ui_data.current_title.clone(), Style::default().fg(Color::Green))];
+    let sort = app.sort_order();
+    if sort != SortOrder::default() {
+        title.push(Span::styled(format!(" [{}]", sort.describe()), Style::default().fg(Color::DarkGray)));
+    }
+    if let Some(filter) = current_directory.filter() {
+        title.push(Span::styled(
+            format!(" {}/{} matching '{}'", current_directory.items().len(), current_directory.total(), filter.query()),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
     let block = Block::default()
-        .title(
-            Span::styled(ui_data.current_title.clone(), Style::default().fg(Color::Green))
-        )
+        .title(Spans::from(title))
         .borders(Borders::ALL);
     f.render_widget(block, chunks[1]);
 
-    let folder_contents = ui_data.current_list.clone();
-    if folder_contents.len() == 0 {
-        draw_empty_dir(f, app, current_block);
+    if current_directory.items().is_empty() {
+        if current_directory.total() > 0 {
+            draw_notice(f, "No matching entries", "Press Esc to clear the filter", current_block);
+        } else {
+            draw_empty_dir(f, app, current_block);
+        }
         return;
     } else {
-        let items: Vec<ListItem> = folder_contents.iter().map(|f| ListItem::new(f.as_str())).collect();
+        let items: Vec<ListItem> = current_directory.items().iter()
+            .zip(current_directory.highlights.iter())
+            .map(|(e, positions)| ListItem::new(with_marker(highlighted_name(e, positions), e, app.selection())))
+            .collect();
         let list = List::new(items)
         .style(Style::default().fg(Color::Gray))
         .highlight_style(Style::default().fg(Color::White).bg(Color::Blue))
         .highlight_symbol("> ");
-        f.render_stateful_widget(list, current_block, current_directory_state);
+        let row = current_directory.selected_row(current_block.height as usize);
+        f.render_stateful_widget(list, current_block, &mut current_directory.state);
+        // Renaming replaces the selected row with the name being edited
+        if let (Some(rename), Some(row)) = (rename, row) {
+            let rect = Rect { y: current_block.y + row as u16, height: 1, ..current_block };
+            f.render_widget(Block::default().style(Style::default().fg(Color::White).bg(Color::Blue)), rect);
+            draw_input(f, vec![Span::raw(">  ")], &rename.input, vec![], rect);
+        }
     }
 
     // child item/dir
-    let block = Block::default()
+    let mut block = Block::default()
         .borders(Borders::ALL);
+    if let Some(file_type) = ui_data.child_type.filter(|_| !ui_data.child_is_folder) {
+        block = block.title(Span::styled(file_type, Style::default().fg(Color::Green)));
+    }
     f.render_widget(block, chunks[2]);
 
-    if let Some(_) = current_directory_state.selected() {
+    if current_directory.state.selected().is_some() {
         if ui_data.child_is_folder {
             if let Ok(folder_contents) = &ui_data.child_list {
-                if folder_contents.len() == 0 {
+                if folder_contents.is_empty() {
                     draw_empty_dir(f, app, contents_block);
                 } else {
-                    let items: Vec<ListItem> = folder_contents.iter().map(|f| ListItem::new(f.as_str())).collect();
+                    let items: Vec<ListItem> = folder_contents.iter().map(|e| ListItem::new(highlighted_name(e, &[]))).collect();
                     let list = List::new(items)
                     .style(Style::default().fg(Color::Gray));
                     f.render_widget(list, contents_block);
                 }
             }
         } else { // is a file
-            if let Some(content) = ui_data.child_content.clone() {
-                let s: Vec<Spans> = content.iter().map(|s| Spans::from(s.as_str())).collect();
-                let paragraph = Paragraph::new(s)
+            let request = (ui_data.preview_scroll, contents_block);
+            if ui_data.preview_requested != Some(request) {
+                if let Some(path) = &ui_data.current_last_selected {
+                    ui_data.previewer.request(path, request.0, contents_block.height as usize, contents_block.width as usize);
+                }
+                ui_data.preview_requested = Some(request);
+            }
+            if ui_data.child_loading {
+                // Blank for the moment it takes to read
+            } else if let Some(content) = ui_data.child_content.clone() {
+                let paragraph = Paragraph::new(content)
                     .wrap(Wrap { trim: false });
                     f.render_widget(paragraph, contents_block);
             } else {
                 let s = vec![
                     Spans::from(Span::styled("Can't display file content", Style::default().add_modifier(Modifier::ITALIC))),
-                    Spans::from(Span::styled("Only text files are supported for preview, or missing filesystem permission", Style::default().fg(Color::DarkGray))),
+                    Spans::from(Span::styled("It's not a regular file, or missing filesystem permission", Style::default().fg(Color::DarkGray))),
                 ];
                 let paragraph = Paragraph::new(s)
                     .alignment(Alignment::Center)
@@ -171,23 +1179,112 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App, redraw_only: bool, ui_d
 }
 
 pub struct Folder {
-    items: Vec<String>,
+    /// The full listing
+    entries: Vec<Entry>,
+    /// The entries passing the filter, these are what the list state indexes into
+    items: Vec<Entry>,
+    /// Matched char positions of every visible item's name
+    highlights: Vec<Vec<usize>>,
+    filter: Option<Filter>,
+    /// Scroll offset of the list, following what the list widget does with the one it keeps to itself
+    offset: usize,
     pub state: ListState
 }
 impl Folder {
-    pub fn new(items: Vec<String>) -> Self {
-        Self {
-            items,
+    pub fn new(items: Vec<Entry>) -> Self {
+        let mut folder = Self {
+            entries: vec![],
+            items: vec![],
+            highlights: vec![],
+            filter: None,
+            offset: 0,
             state: ListState::default(),
-        }
+        };
+        folder.set_items(items);
+        folder
+    }
+    pub fn set_items(&mut self, items: Vec<Entry>) {
+        self.entries = items;
+        self.state = ListState::default();
+        self.offset = 0;
+        self.apply_filter();
+    }
+
+    pub fn items(&self) -> &[Entry] {
+        &self.items
+    }
+
+    /// Total number of entries, including the ones hidden by the filter
+    pub fn total(&self) -> usize {
+        self.entries.len()
     }
-    pub fn set_items(&mut self, items: Vec<String>) {
-        self.items = items;
+
+    /// Hands over the full listing, leaving the folder empty
+    pub fn take_items(&mut self) -> Vec<Entry> {
         self.state = ListState::default();
+        self.items.clear();
+        self.highlights.clear();
+        std::mem::take(&mut self.entries)
+    }
+
+    pub fn filter(&self) -> Option<&Filter> {
+        self.filter.as_ref()
+    }
+
+    pub fn filter_mut(&mut self) -> Option<&mut Filter> {
+        self.filter.as_mut()
+    }
+
+    pub fn set_filter(&mut self, filter: Option<Filter>) {
+        self.filter = filter;
+        self.refilter();
+    }
+
+    /// Re-applies the filter after it changed, keeping the selection if it's still visible
+    pub fn refilter(&mut self) {
+        let selected = self.selected_entry().map(|e| e.path.clone());
+        self.apply_filter();
+        if !selected.is_some_and(|p| self.select_path(&p)) {
+            self.select(Some(0));
+        }
+    }
+
+    fn apply_filter(&mut self) {
+        self.items.clear();
+        self.highlights.clear();
+        for entry in &self.entries {
+            let positions = match &self.filter {
+                Some(filter) => match filter.matches(&entry.name_lossy()) {
+                    Some(positions) => positions,
+                    None => continue,
+                },
+                None => vec![],
+            };
+            self.items.push(entry.clone());
+            self.highlights.push(positions);
+        }
+        if self.state.selected().is_some_and(|i| i >= self.items.len()) {
+            self.state.select(if self.items.is_empty() { None } else { Some(0) });
+        }
+    }
+
+    /// Selects the entry with the given path, returns whether it was found
+    pub fn select_path(&mut self, path: &Path) -> bool {
+        match self.items.iter().position(|e| e.path == path) {
+            Some(i) => {
+                self.state.select(Some(i));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.state.selected().and_then(|i| self.items.get(i))
     }
 
     pub fn next(&mut self) {
-        if self.items.len() == 0 { return }
+        if self.items.is_empty() { return }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -202,7 +1299,7 @@ impl Folder {
     }
 
     pub fn previous(&mut self) {
-        if self.items.len() == 0 { return }
+        if self.items.is_empty() { return }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -220,14 +1317,27 @@ impl Folder {
         self.state.select(None);
     }
 
+    /// Screen row of the selected item in a list of the given height, for drawing over it
+    fn selected_row(&mut self, height: usize) -> Option<usize> {
+        self.offset = self.offset.min(self.items.len().saturating_sub(1));
+        let selected = self.state.selected()?;
+        if selected >= self.offset + height {
+            self.offset = selected + 1 - height;
+        }
+        if selected < self.offset {
+            self.offset = selected;
+        }
+        Some(selected - self.offset)
+    }
+
     pub fn select(&mut self, idx: Option<usize>) {
-        if let Some(idx) = idx {
-            let i = if idx >= self.items.len() {
-                self.items.len()
-            } else if idx <= 0 { 0 } else { idx };
-            self.state.select(Some(i));
-        } else {
-            self.unselect();
+        match idx {
+            Some(_) if self.items.is_empty() => self.unselect(),
+            Some(idx) => {
+                let i = idx.min(self.items.len() - 1);
+                self.state.select(Some(i));
+            }
+            None => self.unselect(),
         }
     }
 }